@@ -20,9 +20,11 @@ import toml
 
 available_options = [
     "manylinux",
+    "manylinux-policy",
     "skip-auditwheel",
     "bindings",
     "strip",
+    "universal2",
     "cargo-extra-args",
     "rustc-extra-args",
 ]
@@ -1,65 +1,20 @@
-use crate::Manylinux;
+use crate::CheckLevel;
+use crate::Policy;
 use crate::Target;
-use failure::Fail;
+use failure::{bail, Error, Fail, ResultExt};
 use goblin;
+use goblin::elf::dynamic::{DT_RPATH, DT_RUNPATH};
+use goblin::elf::header::{EM_386, EM_AARCH64, EM_ARM, EM_PPC64, EM_S390, EM_X86_64};
+use goblin::elf::program_header::{PF_X, PT_GNU_STACK};
 use goblin::elf::Elf;
+use scroll::{Endian, Pread};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
 use std::io;
 use std::io::Read;
 use std::path::Path;
-
-/// As specified in "PEP 513 -- A Platform Tag for Portable Linux Built
-/// Distributions"
-const MANYLINUX1: &[&str] = &[
-    "libpanelw.so.5",
-    "libncursesw.so.5",
-    "libgcc_s.so.1",
-    "libstdc++.so.6",
-    "libm.so.6",
-    "libdl.so.2",
-    "librt.so.1",
-    "libcrypt.so.1",
-    "libc.so.6",
-    "libnsl.so.1",
-    "libutil.so.1",
-    "libpthread.so.0",
-    "libresolv.so.2",
-    "libX11.so.6",
-    "libXext.so.6",
-    "libXrender.so.1",
-    "libICE.so.6",
-    "libSM.so.6",
-    "libGL.so.1",
-    "libgobject-2.0.so.0",
-    "libgthread-2.0.so.0",
-    "libglib-2.0.so.0",
-];
-
-/// As specified in "PEP 571 -- The manylinux2010 Platform Tag"
-///
-/// Currently unused since the python ecosystem is still on manylinux 1
-const MANYLINUX2010: &[&str] = &[
-    "libgcc_s.so.1",
-    "libstdc++.so.6",
-    "libm.so.6",
-    "libdl.so.2",
-    "librt.so.1",
-    "libcrypt.so.1",
-    "libc.so.6",
-    "libnsl.so.1",
-    "libutil.so.1",
-    "libpthread.so.0",
-    "libresolv.so.2",
-    "libX11.so.6",
-    "libXext.so.6",
-    "libXrender.so.1",
-    "libICE.so.6",
-    "libSM.so.6",
-    "libGL.so.1",
-    "libgobject-2.0.so.0",
-    "libgthread-2.0.so.0",
-    "libglib-2.0.so.0",
-];
+use zip::ZipArchive;
 
 /// Error raised duing auditing an elf file for manylinux compatibility
 #[derive(Fail, Debug)]
@@ -78,50 +33,916 @@ pub enum AuditWheelError {
         _0
     )]
     ManylinuxValidationError(Vec<String>),
+    /// The elf file requires symbol versions that are newer than the policy allows, e.g. because
+    /// it was linked against a too recent glibc. Contains the list of offending symbols.
+    #[fail(
+        display = "Your library is not manylinux compliant because it requires the following symbol versions, which are too new: {:?}",
+        _0
+    )]
+    VersionedSymbolTooNewError(Vec<String>),
+    /// The elf file has RPATH or RUNPATH entries that are absolute or point outside of the wheel,
+    /// so they only work on the build machine. Contains the list of offending entries.
+    #[fail(
+        display = "Your library has the following RPATH or RUNPATH entries, which are absolute or point outside of the wheel: {:?}",
+        _0
+    )]
+    RpathError(Vec<String>),
+    /// The elf file has a PT_GNU_STACK segment that is executable
+    #[fail(
+        display = "Your library requires an executable stack, which is a security risk and is refused by hardened systems"
+    )]
+    ExecutableStackError,
+    /// A library for PyPy links libpython or libpypy. PyPy loads extension modules into a process
+    /// that already has all symbols, so linking the library would load a second interpreter.
+    /// Contains the list of offending libraries.
+    #[fail(
+        display = "Your library is built for PyPy, but links the following python libraries: {:?}",
+        _0
+    )]
+    PyPyLinksLibpythonError(Vec<String>),
+    /// A library for PyPy uses symbols of the CPython C API, which PyPy doesn't export under that
+    /// name. PyPy renames the C API to `PyPy*` in its headers. Contains the list of offending
+    /// symbols.
+    #[fail(
+        display = "Your library is built for PyPy, but requires the following CPython symbols: {:?}. Was it compiled against CPython?",
+        _0
+    )]
+    PyPyCPythonSymbolError(Vec<String>),
+}
+
+/// Parses a version such as `2.14` or `3.4.8` into its numeric components, so that versions can
+/// be compared. Returns `None` for versions such as `PRIVATE` that aren't numeric.
+fn parse_symbol_version(version: &str) -> Option<Vec<u32>> {
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// Returns the string at `offset` in the dynamic string table. Unlike indexing the table, this
+/// fails instead of panicking for malformed elf files.
+pub(crate) fn get_dynstr<'a>(
+    elf: &Elf<'a>,
+    offset: usize,
+) -> Result<&'a str, goblin::error::Error> {
+    match elf.dynstrtab.get(offset) {
+        Some(Ok(value)) => Ok(value),
+        Some(Err(err)) => Err(err),
+        None => Err(goblin::error::Error::Malformed(format!(
+            "The offset {} is outside of the dynamic string table",
+            offset
+        ))),
+    }
+}
+
+/// Reads the version needed section (`.gnu.version_r`) and the version symbol section
+/// (`.gnu.version`) and returns all imported symbols that require a specific version, as
+/// `(symbol, version)` pairs, e.g. `("memcpy", "GLIBC_2.14")`
+///
+/// goblin doesn't parse the versioning sections, so we read the raw structures ourselves. They
+/// have the same layout for 32-bit and 64-bit elf files.
+fn find_versioned_symbols(
+    elf: &Elf,
+    buffer: &[u8],
+) -> Result<Vec<(String, String)>, goblin::error::Error> {
+    let info = match elf.dynamic {
+        Some(ref dynamic) => &dynamic.info,
+        None => return Ok(Vec::new()),
+    };
+    if info.verneed == 0 || info.versym == 0 {
+        return Ok(Vec::new());
+    }
+    let endian = if elf.little_endian {
+        Endian::Little
+    } else {
+        Endian::Big
+    };
+
+    // Maps the version index used in .gnu.version to the version name
+    let mut versions = HashMap::new();
+    let mut verneed_offset = info.verneed as usize;
+    for _ in 0..info.verneednum {
+        let vn_cnt: u16 = buffer.pread_with(verneed_offset + 2, endian)?;
+        let vn_aux: u32 = buffer.pread_with(verneed_offset + 8, endian)?;
+        let vn_next: u32 = buffer.pread_with(verneed_offset + 12, endian)?;
+
+        let mut vernaux_offset = verneed_offset + vn_aux as usize;
+        for _ in 0..vn_cnt {
+            let vna_other: u16 = buffer.pread_with(vernaux_offset + 6, endian)?;
+            let vna_name: u32 = buffer.pread_with(vernaux_offset + 8, endian)?;
+            let vna_next: u32 = buffer.pread_with(vernaux_offset + 12, endian)?;
+            versions.insert(vna_other, get_dynstr(elf, vna_name as usize)?.to_string());
+            vernaux_offset += vna_next as usize;
+        }
+
+        if vn_next == 0 {
+            break;
+        }
+        verneed_offset += vn_next as usize;
+    }
+
+    let mut versioned_symbols = Vec::new();
+    for (index, sym) in elf.dynsyms.iter().enumerate() {
+        // Only imported symbols are relevant
+        if sym.st_shndx != 0 {
+            continue;
+        }
+        let versym: u16 = buffer.pread_with(info.versym as usize + 2 * index, endian)?;
+        // The highest bit marks hidden symbols
+        if let Some(version) = versions.get(&(versym & 0x7fff)) {
+            versioned_symbols.push((get_dynstr(elf, sym.st_name)?.to_string(), version.clone()));
+        }
+    }
+
+    Ok(versioned_symbols)
+}
+
+/// Returns all symbols whose version, e.g. `GLIBC_2.14`, is newer than all versions the policy
+/// allows for that version name, formatted as `symbol@version`
+fn find_too_new_symbols(
+    versioned_symbols: &[(String, String)],
+    allowed_versions: &HashMap<String, Vec<String>>,
+) -> Vec<String> {
+    let mut offenders = Vec::new();
+    for (symbol, version) in versioned_symbols {
+        let mut parts = version.rsplitn(2, '_');
+        let (number, name) = match (parts.next(), parts.next()) {
+            (Some(number), Some(name)) => (number, name),
+            _ => continue,
+        };
+        let max_version = match allowed_versions.get(name) {
+            Some(versions) => versions
+                .iter()
+                .filter_map(|version| parse_symbol_version(version))
+                .max(),
+            None => continue,
+        };
+        let too_new = match (parse_symbol_version(number), max_version) {
+            (Some(number), Some(max_version)) => number > max_version,
+            // e.g. glibc symbols for musllinux
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if too_new {
+            offenders.push(format!("{}@{}", symbol, version));
+        }
+    }
+    offenders.sort();
+    offenders.dedup();
+    offenders
+}
+
+/// Returns the DT_RPATH and DT_RUNPATH entries of the elf file
+pub(crate) fn get_rpaths(elf: &Elf) -> Result<Vec<String>, goblin::error::Error> {
+    let mut rpaths = Vec::new();
+    if let Some(ref dynamic) = elf.dynamic {
+        for dyn_ in &dynamic.dyns {
+            if dyn_.d_tag == DT_RPATH || dyn_.d_tag == DT_RUNPATH {
+                let value = get_dynstr(elf, dyn_.d_val as usize)?;
+                rpaths.extend(value.split(':').map(ToString::to_string));
+            }
+        }
+    }
+    Ok(rpaths)
+}
+
+/// Returns true if the rpath only works on the build machine, i.e. if it is absolute (or
+/// relative to the working directory) or if it is relative to `$ORIGIN` but leaves the wheel.
+/// `depth` is the number of directories between the library and the root of the wheel.
+fn is_outside_wheel(rpath: &str, depth: usize) -> bool {
+    let relative = match rpath
+        .strip_prefix("$ORIGIN")
+        .or_else(|| rpath.strip_prefix("${ORIGIN}"))
+    {
+        Some(relative) => relative,
+        None => return true,
+    };
+    let mut level = depth as isize;
+    for part in relative.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                level -= 1;
+                if level < 0 {
+                    return true;
+                }
+            }
+            _ => level += 1,
+        }
+    }
+    false
+}
+
+/// Returns true if a PT_GNU_STACK program header marks the stack as executable
+fn has_executable_stack(elf: &Elf) -> bool {
+    elf.program_headers
+        .iter()
+        .any(|header| header.p_type == PT_GNU_STACK && header.p_flags & PF_X != 0)
+}
+
+/// Returns the error for [CheckLevel::Error] and only prints it as warning for [CheckLevel::Warn]
+fn apply_check_level(level: CheckLevel, error: AuditWheelError) -> Result<(), AuditWheelError> {
+    match level {
+        CheckLevel::Error => Err(error),
+        CheckLevel::Warn => {
+            eprintln!("⚠ Warning: {}", error);
+            Ok(())
+        }
+    }
+}
+
+/// Returns the libraries marked as NEEDED which the policy doesn't whitelist
+pub(crate) fn find_external_libs(elf: &Elf, policy: &Policy) -> Vec<String> {
+    // This returns essentially the same as ldd
+    let deps: Vec<String> = elf.libraries.iter().map(ToString::to_string).collect();
+
+    let mut offenders = Vec::new();
+    for dep in deps {
+        // The dynamic loader is part of every system, so auditwheel skips it, e.g.
+        // ld-linux-aarch64.so.1, ld-linux-armhf.so.3 or ld64.so.2 on ppc64le
+        if dep.starts_with("ld-linux") || dep.starts_with("ld64.so") || dep.starts_with("ld-musl-")
+        {
+            continue;
+        }
+        if !policy.lib_whitelist.contains(&dep) {
+            offenders.push(dep);
+        }
+    }
+    offenders
 }
 
 /// An (incomplete) reimplementation of auditwheel, which checks elf files for
 /// manylinux compliance. Returns an error for non compliant elf files
 ///
-/// Only checks for the libraries marked as NEEDED, but not for symbol versions
-/// (e.g. requiring a too recent glibc isn't caught).
+/// Checks the libraries marked as NEEDED and the versions of the glibc, libstdc++ and libgcc
+/// symbols (e.g. requiring a too recent glibc is caught) against the policy. It also checks for
+/// RPATH and RUNPATH entries that point outside of the wheel and for executable stacks, which
+/// depending on the policy are errors or only warnings.
+///
+/// `depth` is the number of directories between the library and the root of the wheel. In repair
+/// mode, libraries that aren't whitelisted are allowed since they will be bundled into the wheel,
+/// and the RPATH and RUNPATH entries are ignored since they will be replaced.
 pub fn auditwheel_rs(
     path: &Path,
     target: &Target,
-    manylinux: &Manylinux,
+    policy: &Policy,
+    repair: bool,
+    depth: usize,
 ) -> Result<(), AuditWheelError> {
     if !target.is_linux() {
         return Ok(());
     }
-    let reference: &[&str];
-    match *manylinux {
-        Manylinux::Manylinux1 => reference = MANYLINUX1,
-        Manylinux::Manylinux2010 => reference = MANYLINUX2010,
-        _ => return Ok(()),
-    };
     let mut file = File::open(path).map_err(AuditWheelError::IOError)?;
     let mut buffer = Vec::new();
     file.read_to_end(&mut buffer)
         .map_err(AuditWheelError::IOError)?;
-    let elf = Elf::parse(&buffer).map_err(AuditWheelError::GoblinError)?;
-    // This returns essentially the same as ldd
-    let deps: Vec<String> = elf.libraries.iter().map(ToString::to_string).collect();
+    audit_buffer(&buffer, target, policy, repair, depth)
+}
 
-    let mut offenders = Vec::new();
-    for dep in deps {
-        // I'm not 100% what exactely this line does, but auditwheel also seems to skip
-        // everything with ld-linux in its name
-        if dep == "ld-linux-x86-64.so.2" || dep == "ld-linux.so.2" {
-            continue;
+/// Runs the checks of [auditwheel_rs()] on an elf file that was already read into memory
+fn audit_buffer(
+    buffer: &[u8],
+    target: &Target,
+    policy: &Policy,
+    repair: bool,
+    depth: usize,
+) -> Result<(), AuditWheelError> {
+    let elf = Elf::parse(buffer).map_err(AuditWheelError::GoblinError)?;
+
+    let offenders = find_external_libs(&elf, policy);
+    if !offenders.is_empty() && !repair {
+        return Err(AuditWheelError::ManylinuxValidationError(offenders));
+    }
+
+    let versioned_symbols =
+        find_versioned_symbols(&elf, buffer).map_err(AuditWheelError::GoblinError)?;
+    if let Some(allowed_versions) = policy.symbol_versions_for(target.get_linux_arch()) {
+        let too_new = find_too_new_symbols(&versioned_symbols, allowed_versions);
+        if !too_new.is_empty() {
+            return Err(AuditWheelError::VersionedSymbolTooNewError(too_new));
         }
-        if !reference.contains(&dep.as_str()) {
-            offenders.push(dep);
+    }
+
+    if !repair {
+        let rpaths: Vec<String> = get_rpaths(&elf)
+            .map_err(AuditWheelError::GoblinError)?
+            .into_iter()
+            .filter(|rpath| is_outside_wheel(rpath, depth))
+            .collect();
+        if !rpaths.is_empty() {
+            apply_check_level(policy.rpath, AuditWheelError::RpathError(rpaths))?;
         }
     }
 
-    if offenders.is_empty() {
-        Ok(())
+    if has_executable_stack(&elf) {
+        apply_check_level(
+            policy.executable_stack,
+            AuditWheelError::ExecutableStackError,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Returns the undefined dynamic symbols of the CPython C API, i.e. those that start with `Py` or
+/// `_Py`, but not `PyPy` or `_PyPy`
+fn find_cpython_symbols(elf: &Elf) -> Vec<String> {
+    elf.dynsyms
+        .iter()
+        .filter(|sym| sym.st_shndx == 0)
+        .filter_map(|sym| elf.dynstrtab.get(sym.st_name))
+        .filter_map(Result::ok)
+        .filter(|name| {
+            (name.starts_with("Py") || name.starts_with("_Py"))
+                && !(name.starts_with("PyPy") || name.starts_with("_PyPy"))
+        })
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Returns the libraries marked as NEEDED that are a libpython or a libpypy
+fn find_python_libs(elf: &Elf) -> Vec<String> {
+    elf.libraries
+        .iter()
+        .filter(|lib| lib.starts_with("libpython") || lib.starts_with("libpypy"))
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Checks that a native library can be loaded by PyPy, i.e. that it neither links libpython or
+/// libpypy nor requires symbols of the CPython C API, which happens when it was compiled against
+/// CPython's headers. This is independent of the manylinux policy, so it's also done for the native
+/// linux tag.
+pub fn audit_pypy(path: &Path, target: &Target) -> Result<(), AuditWheelError> {
+    if !target.is_linux() && !target.is_bsd() {
+        return Ok(());
+    }
+    let mut file = File::open(path).map_err(AuditWheelError::IOError)?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)
+        .map_err(AuditWheelError::IOError)?;
+    audit_pypy_buffer(&buffer)
+}
+
+/// Runs the checks of [audit_pypy()] on an elf file that was already read into memory
+fn audit_pypy_buffer(buffer: &[u8]) -> Result<(), AuditWheelError> {
+    let elf = Elf::parse(buffer).map_err(AuditWheelError::GoblinError)?;
+
+    let python_libs = find_python_libs(&elf);
+    if !python_libs.is_empty() {
+        return Err(AuditWheelError::PyPyLinksLibpythonError(python_libs));
+    }
+
+    let cpython_symbols = find_cpython_symbols(&elf);
+    if !cpython_symbols.is_empty() {
+        return Err(AuditWheelError::PyPyCPythonSymbolError(cpython_symbols));
+    }
+
+    Ok(())
+}
+
+/// The result of auditing a native library against all policies
+pub struct PolicyAudit<'a> {
+    /// The strictest policy the library complies with, or `None` if it complies with none of
+    /// them
+    pub policy: Option<&'a Policy>,
+    /// The stricter policies that were rejected, together with the reason
+    pub rejected: Vec<(&'a Policy, AuditWheelError)>,
+}
+
+/// Audits the elf file against the policies from the strictest (i.e. highest priority) to the most
+/// permissive one and stops at the first policy it complies with. Policies with priority 0,
+/// i.e. the native linux tag, aren't checked.
+///
+/// Errors that aren't about compliance, e.g. an unreadable file, are returned directly. `depth` is
+/// the number of directories between the library and the root of the wheel.
+pub fn find_best_policy<'a>(
+    path: &Path,
+    target: &Target,
+    policies: &'a [Policy],
+    depth: usize,
+) -> Result<PolicyAudit<'a>, AuditWheelError> {
+    let mut file = File::open(path).map_err(AuditWheelError::IOError)?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)
+        .map_err(AuditWheelError::IOError)?;
+    find_best_policy_for_buffer(&buffer, target, policies, depth)
+}
+
+/// [find_best_policy()] for an elf file that was already read into memory
+fn find_best_policy_for_buffer<'a>(
+    buffer: &[u8],
+    target: &Target,
+    policies: &'a [Policy],
+    depth: usize,
+) -> Result<PolicyAudit<'a>, AuditWheelError> {
+    let arch = target.get_linux_arch();
+    let mut candidates: Vec<&Policy> = policies
+        .iter()
+        .filter(|x| x.priority > 0 && x.supports_arch(arch) && x.is_musl() == target.is_musl())
+        .collect();
+    candidates.sort_by_key(|policy| -policy.priority);
+
+    let mut rejected = Vec::new();
+    for policy in candidates {
+        match audit_buffer(buffer, target, policy, false, depth) {
+            Ok(()) => {
+                return Ok(PolicyAudit {
+                    policy: Some(policy),
+                    rejected,
+                })
+            }
+            Err(err @ AuditWheelError::IOError(_)) | Err(err @ AuditWheelError::GoblinError(_)) => {
+                return Err(err)
+            }
+            Err(err) => rejected.push((policy, err)),
+        }
+    }
+    Ok(PolicyAudit {
+        policy: None,
+        rejected,
+    })
+}
+
+/// What `pyo3-pack audit` found out about an elf file inside a wheel
+#[derive(Serialize, Debug, Clone)]
+pub struct ElfAudit {
+    /// The path of the file inside the wheel
+    pub path: String,
+    /// The libraries marked as NEEDED
+    pub needed: Vec<String>,
+    /// The required symbol versions by version name, e.g. `GLIBC` to `["2.2.5", "2.14"]`
+    pub symbol_versions: BTreeMap<String, Vec<String>>,
+    /// The strictest policy the file complies with, if any
+    pub policy: Option<String>,
+    /// The stricter policies that were rejected, together with the reason
+    pub rejected: BTreeMap<String, String>,
+    /// Why the file can't be loaded by PyPy, if the wheel is for PyPy
+    pub pypy_error: Option<String>,
+}
+
+/// What `pyo3-pack audit` found out about a wheel
+#[derive(Serialize, Debug, Clone)]
+pub struct WheelAudit {
+    /// The elf files in the wheel
+    pub files: Vec<ElfAudit>,
+    /// The strictest policy all elf files comply with, if any
+    pub policy: Option<String>,
+}
+
+/// Returns the target an elf file was built for, which isn't necessarily the current one when
+/// auditing wheels from somewhere else
+fn target_from_elf(elf: &Elf) -> Result<Target, Error> {
+    let is_musl = elf.interpreter.iter().any(|x| x.contains("ld-musl"))
+        || elf.libraries.iter().any(|x| x.starts_with("libc.musl-"));
+    let triple = match (elf.header.e_machine, is_musl) {
+        (EM_X86_64, false) => "x86_64-unknown-linux-gnu",
+        (EM_X86_64, true) => "x86_64-unknown-linux-musl",
+        (EM_386, false) => "i686-unknown-linux-gnu",
+        (EM_386, true) => "i686-unknown-linux-musl",
+        (EM_AARCH64, false) => "aarch64-unknown-linux-gnu",
+        (EM_AARCH64, true) => "aarch64-unknown-linux-musl",
+        (EM_ARM, false) => "armv7-unknown-linux-gnueabihf",
+        (EM_ARM, true) => "armv7-unknown-linux-musleabihf",
+        (EM_PPC64, false) if elf.little_endian => "powerpc64le-unknown-linux-gnu",
+        (EM_S390, false) => "s390x-unknown-linux-gnu",
+        (other, _) => bail!("The elf machine type {} is not supported", other),
+    };
+    Target::from_target_triple(Some(triple.to_string()))
+}
+
+/// Groups the versions of the versioned symbols by version name and sorts them, e.g.
+/// `GLIBC_2.2.5` and `GLIBC_2.14` become `GLIBC` to `["2.2.5", "2.14"]`
+fn group_symbol_versions(versioned_symbols: &[(String, String)]) -> BTreeMap<String, Vec<String>> {
+    let mut grouped: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (_, version) in versioned_symbols {
+        let mut parts = version.rsplitn(2, '_');
+        if let (Some(number), Some(name)) = (parts.next(), parts.next()) {
+            let versions = grouped.entry(name.to_string()).or_default();
+            if !versions.iter().any(|x| x == number) {
+                versions.push(number.to_string());
+            }
+        }
+    }
+    for versions in grouped.values_mut() {
+        // Versions that aren't numeric, such as `PRIVATE`, go last
+        versions.sort_by_key(|version| {
+            (
+                parse_symbol_version(version).is_none(),
+                parse_symbol_version(version),
+            )
+        });
+    }
+    grouped
+}
+
+/// Returns whether the wheel is for PyPy, i.e. whether the python tag of the filename is `pp*`,
+/// e.g. `test-1.0-pp37-pypy37_pp73-linux_x86_64.whl`
+fn is_pypy_wheel(wheel: &Path) -> bool {
+    wheel
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| stem.rsplit('-').nth(2))
+        .is_some_and(|python_tag| python_tag.starts_with("pp"))
+}
+
+/// Returns the sonames of the elf files inside the wheel, falling back to the filename for
+/// libraries without a soname. These are e.g. the libraries bundled in `<module>.libs` by repair mode.
+fn find_bundled_sonames(elf_files: &[(String, Vec<u8>)]) -> Result<HashSet<String>, Error> {
+    let mut sonames = HashSet::new();
+    for (path, buffer) in elf_files {
+        let elf = Elf::parse(buffer).context(format!("Failed to parse {}", path))?;
+        match elf.soname {
+            Some(soname) => sonames.insert(soname.to_string()),
+            None => sonames.insert(path.rsplit('/').next().unwrap_or(path).to_string()),
+        };
+    }
+    Ok(sonames)
+}
+
+/// Checks every elf file inside the wheel against the policies, like `auditwheel show`. Files in
+/// PyPy wheels are also checked with [audit_pypy()].
+///
+/// NEEDED libraries that are shipped inside the wheel, e.g. those bundled by repair mode, are
+/// resolved by their soname and don't need to be whitelisted; they are audited themselves instead.
+pub fn audit_wheel(wheel: &Path, policies: &[Policy]) -> Result<WheelAudit, Error> {
+    let is_pypy = is_pypy_wheel(wheel);
+    let file = File::open(wheel).context(format!("Failed to open {}", wheel.display()))?;
+    let mut archive = ZipArchive::new(file).context("The wheel is not a valid zip file")?;
+
+    let mut elf_files = Vec::new();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let mut buffer = Vec::new();
+        entry.read_to_end(&mut buffer)?;
+        if buffer.starts_with(b"\x7fELF") {
+            elf_files.push((entry.name().to_string(), buffer));
+        }
+    }
+
+    let bundled = find_bundled_sonames(&elf_files)?;
+    let policies: Vec<Policy> = policies
+        .iter()
+        .cloned()
+        .map(|mut policy| {
+            policy.lib_whitelist.extend(bundled.iter().cloned());
+            policy
+        })
+        .collect();
+
+    let mut files = Vec::new();
+    let mut file_policies = Vec::new();
+    for (path, buffer) in elf_files {
+        let elf = Elf::parse(&buffer).context(format!("Failed to parse {}", path))?;
+        let target = target_from_elf(&elf)?;
+        let versioned_symbols = find_versioned_symbols(&elf, &buffer)?;
+        let depth = path.matches('/').count();
+        let audit = find_best_policy_for_buffer(&buffer, &target, &policies, depth)?;
+        let pypy_error = if is_pypy {
+            match audit_pypy_buffer(&buffer) {
+                Ok(()) => None,
+                Err(err @ AuditWheelError::IOError(_))
+                | Err(err @ AuditWheelError::GoblinError(_)) => return Err(err.into()),
+                Err(err) => Some(err.to_string()),
+            }
+        } else {
+            None
+        };
+
+        file_policies.push(audit.policy);
+        files.push(ElfAudit {
+            path,
+            needed: elf.libraries.iter().map(ToString::to_string).collect(),
+            symbol_versions: group_symbol_versions(&versioned_symbols),
+            policy: audit.policy.map(|policy| policy.name.clone()),
+            rejected: audit
+                .rejected
+                .iter()
+                .map(|(policy, reason)| (policy.name.clone(), reason.to_string()))
+                .collect(),
+            pypy_error,
+        });
+    }
+
+    // The wheel complies with the most permissive of the policies of the individual files
+    let policy = if file_policies.iter().all(Option::is_some) {
+        file_policies
+            .into_iter()
+            .flatten()
+            .min_by_key(|policy| policy.priority)
+            .map(|policy| policy.name.clone())
     } else {
-        Err(AuditWheelError::ManylinuxValidationError(offenders))
+        None
+    };
+
+    Ok(WheelAudit { files, policy })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The fixtures were built with
+    /// `gcc -shared -fPIC -O0 -fno-builtin -s -o <library> <source>.c` against glibc 2.36, where
+    /// libversioned only calls `memcpy` and libcompliant only calls `strlen`
+    #[test]
+    fn test_symbol_versions() {
+        let target =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        let policies = Policy::embedded();
+        let manylinux1 = Policy::find(&policies, "manylinux1").unwrap();
+
+        match auditwheel_rs(
+            Path::new("test-data/libversioned.so.1"),
+            &target,
+            manylinux1,
+            false,
+            0,
+        ) {
+            Err(AuditWheelError::VersionedSymbolTooNewError(symbols)) => {
+                assert_eq!(symbols, vec!["memcpy@GLIBC_2.14".to_string()])
+            }
+            other => panic!("Expected a VersionedSymbolTooNewError, got {:?}", other),
+        }
+
+        let manylinux2014 = Policy::find(&policies, "manylinux2014").unwrap();
+        assert!(auditwheel_rs(
+            Path::new("test-data/libversioned.so.1"),
+            &target,
+            manylinux2014,
+            false,
+            0
+        )
+        .is_ok());
+
+        // A user supplied policy can allow newer symbol versions
+        let mut custom = manylinux1.clone();
+        custom
+            .symbol_versions
+            .get_mut("x86_64")
+            .unwrap()
+            .get_mut("GLIBC")
+            .unwrap()
+            .push("2.14".to_string());
+        assert!(auditwheel_rs(
+            Path::new("test-data/libversioned.so.1"),
+            &target,
+            &custom,
+            false,
+            0
+        )
+        .is_ok());
+        assert!(auditwheel_rs(
+            Path::new("test-data/libcompliant.so.1"),
+            &target,
+            manylinux1,
+            false,
+            0
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_is_outside_wheel() {
+        assert!(is_outside_wheel("/opt/build/lib", 0));
+        assert!(is_outside_wheel("lib", 0));
+        assert!(!is_outside_wheel("$ORIGIN", 0));
+        assert!(!is_outside_wheel("$ORIGIN/../foo.libs", 1));
+        assert!(!is_outside_wheel("${ORIGIN}/foo/../../bar", 1));
+        assert!(is_outside_wheel("$ORIGIN/../foo.libs", 0));
+        assert!(is_outside_wheel("$ORIGIN/../../lib", 1));
+    }
+
+    /// libinsecure was built with `-Wl,-rpath,/opt/build/lib -Wl,--disable-new-dtags
+    /// -Wl,-z,execstack`
+    #[test]
+    fn test_rpath_and_executable_stack() {
+        let target =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        let policies = Policy::embedded();
+        let mut policy = Policy::find(&policies, "manylinux1").unwrap().clone();
+        let insecure = Path::new("test-data/libinsecure.so.1");
+
+        match auditwheel_rs(insecure, &target, &policy, false, 0) {
+            Err(AuditWheelError::RpathError(rpaths)) => {
+                assert_eq!(rpaths, vec!["/opt/build/lib".to_string()])
+            }
+            other => panic!("Expected a RpathError, got {:?}", other),
+        }
+        // The rpath will be replaced in repair mode
+        match auditwheel_rs(insecure, &target, &policy, true, 0) {
+            Err(AuditWheelError::ExecutableStackError) => {}
+            other => panic!("Expected an ExecutableStackError, got {:?}", other),
+        }
+
+        policy.rpath = CheckLevel::Warn;
+        policy.executable_stack = CheckLevel::Warn;
+        assert!(auditwheel_rs(insecure, &target, &policy, false, 0).is_ok());
+    }
+
+    /// The fixture was built with rust-lld for aarch64 against stubs with the sonames `libc.so.6`
+    /// and `ld-linux-aarch64.so.1`, since the dynamic loader has a different name on every
+    /// architecture
+    #[test]
+    fn test_dynamic_loader() {
+        let target =
+            Target::from_target_triple(Some("aarch64-unknown-linux-gnu".to_string())).unwrap();
+        let policies = Policy::embedded();
+        let manylinux2014 = Policy::find(&policies, "manylinux2014").unwrap();
+
+        let libaarch64 = Path::new("test-data/libaarch64.so.1");
+        assert!(auditwheel_rs(libaarch64, &target, manylinux2014, false, 0).is_ok());
+
+        let buffer = std::fs::read(libaarch64).unwrap();
+        let elf = Elf::parse(&buffer).unwrap();
+        assert!(elf.libraries.contains(&"ld-linux-aarch64.so.1"));
+        assert_eq!(target_from_elf(&elf).unwrap(), target);
+    }
+
+    #[test]
+    fn test_musllinux() {
+        let musl =
+            Target::from_target_triple(Some("x86_64-unknown-linux-musl".to_string())).unwrap();
+        let policies = Policy::embedded();
+        let musllinux = Policy::find(&policies, "musllinux_1_1").unwrap();
+
+        let libmusl = Path::new("test-data/libmusl.so.1");
+        assert!(auditwheel_rs(libmusl, &musl, musllinux, false, 0).is_ok());
+        let audit = find_best_policy(libmusl, &musl, &policies, 0).unwrap();
+        assert_eq!(audit.policy.map(|x| x.name.as_str()), Some("musllinux_1_1"));
+        assert!(audit.rejected.is_empty());
+
+        // glibc libraries are rejected by soname and by their symbol versions
+        let glibc = Path::new("test-data/libversioned.so.1");
+        match auditwheel_rs(glibc, &musl, musllinux, false, 0) {
+            Err(AuditWheelError::ManylinuxValidationError(libs)) => {
+                assert!(libs.contains(&"libc.so.6".to_string()))
+            }
+            other => panic!("Expected a ManylinuxValidationError, got {:?}", other),
+        }
+        match auditwheel_rs(glibc, &musl, musllinux, true, 0) {
+            Err(AuditWheelError::VersionedSymbolTooNewError(symbols)) => {
+                assert!(symbols.contains(&"memcpy@GLIBC_2.14".to_string()))
+            }
+            other => panic!("Expected a VersionedSymbolTooNewError, got {:?}", other),
+        }
+
+        // The musl policy isn't a candidate for glibc targets
+        let gnu = Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        let audit = find_best_policy(libmusl, &gnu, &policies, 0).unwrap();
+        assert!(audit.rejected.iter().all(|(policy, _)| !policy.is_musl()));
+    }
+
+    #[test]
+    fn test_find_best_policy() {
+        let target =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        let policies = Policy::embedded();
+
+        let audit = find_best_policy(
+            Path::new("test-data/libversioned.so.1"),
+            &target,
+            &policies,
+            0,
+        )
+        .unwrap();
+        assert_eq!(audit.policy.unwrap().name, "manylinux2014");
+        let rejected: Vec<&str> = audit
+            .rejected
+            .iter()
+            .map(|(x, _)| x.name.as_str())
+            .collect();
+        assert_eq!(rejected, vec!["manylinux1", "manylinux2010"]);
+
+        let audit = find_best_policy(
+            Path::new("test-data/libcompliant.so.1"),
+            &target,
+            &policies,
+            0,
+        )
+        .unwrap();
+        assert_eq!(audit.policy.unwrap().name, "manylinux1");
+        assert!(audit.rejected.is_empty());
+
+        // libuser links libdep, which no policy whitelists
+        let audit =
+            find_best_policy(Path::new("test-data/libuser.so.1"), &target, &policies, 0).unwrap();
+        assert!(audit.policy.is_none());
+        assert_eq!(audit.rejected.len(), 3);
+    }
+
+    #[test]
+    fn test_audit_wheel() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let wheel = tempdir.path().join("test-1.0-py3-none-linux_x86_64.whl");
+        let mut writer = zip::ZipWriter::new(File::create(&wheel).unwrap());
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for (name, source) in &[
+            ("test/libcompliant.so", "test-data/libcompliant.so.1"),
+            ("test/libversioned.so", "test-data/libversioned.so.1"),
+        ] {
+            writer.start_file(*name, options).unwrap();
+            std::io::Write::write_all(&mut writer, &std::fs::read(source).unwrap()).unwrap();
+        }
+        writer.start_file("test/__init__.py", options).unwrap();
+        writer.finish().unwrap();
+
+        let audit = audit_wheel(&wheel, &Policy::embedded()).unwrap();
+        assert_eq!(audit.policy, Some("manylinux2014".to_string()));
+        assert_eq!(audit.files.len(), 2);
+        assert_eq!(audit.files[0].policy, Some("manylinux1".to_string()));
+        assert_eq!(audit.files[1].needed, vec!["libc.so.6"]);
+        assert_eq!(
+            audit.files[1].symbol_versions["GLIBC"],
+            vec!["2.2.5", "2.14"]
+        );
+        assert_eq!(
+            audit.files[1].rejected.keys().collect::<Vec<_>>(),
+            vec!["manylinux1", "manylinux2010"]
+        );
+        assert_eq!(audit.files[0].pypy_error, None);
+    }
+
+    /// A wheel with a library repaired by [crate::repair::repair()], whose dependency is bundled in
+    /// `test.libs`, must comply with the policy the library was repaired for
+    #[test]
+    fn test_audit_repaired_wheel() {
+        let tempdir = tempfile::tempdir().unwrap();
+        for library in &["libdep.so.1", "libuser.so.1"] {
+            std::fs::copy(
+                Path::new("test-data").join(library),
+                tempdir.path().join(library),
+            )
+            .unwrap();
+        }
+        let target =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        let policies = Policy::embedded();
+        let manylinux1 = Policy::find(&policies, "manylinux1").unwrap();
+        let repaired = crate::repair::repair(
+            &tempdir.path().join("libuser.so.1"),
+            &target,
+            manylinux1,
+            "$ORIGIN/../test.libs",
+        )
+        .unwrap()
+        .unwrap();
+
+        let wheel = tempdir.path().join("test-1.0-py3-none-linux_x86_64.whl");
+        let mut writer = zip::ZipWriter::new(File::create(&wheel).unwrap());
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        writer.start_file("test/libuser.so", options).unwrap();
+        std::io::Write::write_all(&mut writer, &std::fs::read(&repaired.artifact).unwrap())
+            .unwrap();
+        for (soname, content) in &repaired.libraries {
+            writer
+                .start_file(format!("test.libs/{}", soname), options)
+                .unwrap();
+            std::io::Write::write_all(&mut writer, content).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let audit = audit_wheel(&wheel, &policies).unwrap();
+        assert_eq!(audit.files.len(), 2);
+        assert_eq!(audit.files[0].policy, Some("manylinux1".to_string()));
+        assert_eq!(audit.policy, Some("manylinux1".to_string()));
+    }
+
+    /// The fixtures were built with `gcc -shared -fPIC -O0 -s -nostdlib`. libpypyapi uses
+    /// `PyPyLong_FromLong` and `_PyPy_NoneStruct` like a library compiled against PyPy's headers.
+    /// libcpythonapi additionally uses `PyLong_FromLong` and `_Py_NoneStruct` and links a stub
+    /// with the soname `libpython3.7m.so.1.0`, like a library compiled against CPython's headers.
+    #[test]
+    fn test_audit_pypy() {
+        let target =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+
+        assert!(audit_pypy(Path::new("test-data/libpypyapi.so.1"), &target).is_ok());
+        match audit_pypy(Path::new("test-data/libcpythonapi.so.1"), &target) {
+            Err(AuditWheelError::PyPyLinksLibpythonError(libs)) => {
+                assert_eq!(libs, vec!["libpython3.7m.so.1.0".to_string()])
+            }
+            other => panic!("Expected a PyPyLinksLibpythonError, got {:?}", other),
+        }
+
+        let buffer = std::fs::read("test-data/libcpythonapi.so.1").unwrap();
+        let elf = Elf::parse(&buffer).unwrap();
+        let mut symbols = find_cpython_symbols(&elf);
+        symbols.sort();
+        assert_eq!(symbols, vec!["PyLong_FromLong", "_Py_NoneStruct"]);
+
+        let windows =
+            Target::from_target_triple(Some("x86_64-pc-windows-msvc".to_string())).unwrap();
+        assert!(audit_pypy(Path::new("test-data/libcpythonapi.so.1"), &windows).is_ok());
+    }
+
+    #[test]
+    fn test_is_pypy_wheel() {
+        assert!(is_pypy_wheel(Path::new(
+            "dist/test-1.0-pp37-pypy37_pp73-manylinux2010_x86_64.whl"
+        )));
+        assert!(!is_pypy_wheel(Path::new(
+            "dist/test-1.0-cp37-cp37m-manylinux2010_x86_64.whl"
+        )));
+        assert!(!is_pypy_wheel(Path::new("test.whl")));
     }
 }
@@ -1,20 +1,30 @@
 #[cfg(feature = "auditwheel")]
-use crate::auditwheel::auditwheel_rs;
+use crate::auditwheel::{audit_pypy, auditwheel_rs, find_best_policy};
 use crate::compile;
-use crate::compile::warn_missing_py_init;
+use crate::compile::{compile_captured, CapturedBuild};
+use crate::compile::{get_macos_version, warn_missing_py_init};
 use crate::module_writer::write_python_part;
 use crate::module_writer::WheelWriter;
-use crate::module_writer::{write_bin, write_bindings_module, write_cffi_module};
+use crate::module_writer::{
+    write_bin, write_bindings_module, write_bundled_libraries, write_cffi_module, BundledLibraries,
+};
+use crate::python_interpreter::Interpreter;
+#[cfg(feature = "auditwheel")]
+use crate::repair::repair;
 use crate::source_distribution::{get_pyproject_toml, source_distribution};
 use crate::Manylinux;
 use crate::Metadata21;
+use crate::Policy;
 use crate::PythonInterpreter;
 use crate::Target;
-use cargo_metadata::Metadata;
-use failure::{bail, Context, Error, ResultExt};
-use std::collections::HashMap;
+use cargo_metadata::{Metadata, PackageId};
+use failure::{bail, format_err, Context, Error, ResultExt};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
 
 /// The way the rust code is used in the wheel
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -26,6 +36,9 @@ pub enum BridgeModel {
     /// A native module with pyo3 or rust-cpython bindings. The String is the name of the bindings
     /// providing crate, e.g. pyo3.
     Bindings(String),
+    /// A native module with pyo3 bindings that only uses the stable abi (abi3), so a single wheel
+    /// works for all python versions starting with the given major and minor version
+    BindingsAbi3(u8, u8),
 }
 
 impl BridgeModel {
@@ -33,9 +46,18 @@ impl BridgeModel {
     pub fn unwrap_bindings(&self) -> &str {
         match self {
             BridgeModel::Bindings(value) => &value,
+            BridgeModel::BindingsAbi3(_, _) => "pyo3",
             _ => panic!("Expected Bindings"),
         }
     }
+
+    /// Returns true if this is a pyo3 or rust-cpython native module, with or without abi3
+    pub fn is_bindings(&self) -> bool {
+        matches!(
+            self,
+            BridgeModel::Bindings(_) | BridgeModel::BindingsAbi3(_, _)
+        )
+    }
 }
 
 /// Whether this project is pure rust or rust mixed with python
@@ -86,8 +108,17 @@ pub struct BuildContext {
     /// because package names normally contain minuses while module names
     /// have underscores. The package name is part of metadata21
     pub module_name: String,
-    /// The path to the Cargo.toml. Required for the cargo invocations
+    /// The path to the Cargo.toml. Required for the cargo invocations. In a workspace, this is
+    /// the Cargo.toml of the selected member
     pub manifest_path: PathBuf,
+    /// The id of the package that is built, which identifies its artifacts in the output of cargo
+    pub package_id: PackageId,
+    /// The names of the binaries that are put into the wheel, either from `--bin`, from
+    /// `bins` in `[package.metadata.pyo3-pack]` or all binaries of the package
+    pub bins: Vec<String>,
+    /// The cargo features from `features` in `[package.metadata.pyo3-pack]`, which are enabled
+    /// for all builds of the package
+    pub features: Vec<String>,
     /// The directory to store the built wheels in. Defaults to a new "wheels"
     /// directory in the project's target directory
     pub out: PathBuf,
@@ -98,6 +129,8 @@ pub struct BuildContext {
     /// Whether to use the the manylinux and check compliance (on), use it but don't
     /// check compliance (no-auditwheel) or use the native linux tag (off)
     pub manylinux: Manylinux,
+    /// The manylinux policies, either the embedded ones or the ones from `--manylinux-policy`
+    pub policies: Vec<Policy>,
     /// Extra arguments that will be passed to cargo as `cargo rustc [...] [arg1] [arg2] --`
     pub cargo_extra_args: Vec<String>,
     /// Extra arguments that will be passed to rustc as `cargo rustc [...] -- [arg1] [arg2]`
@@ -106,6 +139,8 @@ pub struct BuildContext {
     pub interpreter: Vec<PythonInterpreter>,
     /// Cargo.toml as resolved by [cargo_metadata]
     pub cargo_metadata: Metadata,
+    /// The number of interpreters to build wheels for at the same time
+    pub jobs: usize,
 }
 
 type BuiltWheelMetadata = (PathBuf, String, Option<PythonInterpreter>);
@@ -122,6 +157,9 @@ impl BuildContext {
             BridgeModel::Cffi => vec![(self.build_cffi_wheel()?, "py2.py3".to_string(), None)],
             BridgeModel::Bin => vec![(self.build_bin_wheel()?, "py2.py3".to_string(), None)],
             BridgeModel::Bindings(_) => self.build_binding_wheels()?,
+            BridgeModel::BindingsAbi3(major, minor) => {
+                vec![self.build_binding_wheel_abi3(*major, *minor)?]
+            }
         };
 
         Ok(wheels)
@@ -148,53 +186,257 @@ impl BuildContext {
     /// and silently ignores all non-existent python versions.
     ///
     /// Runs [auditwheel_rs()] if not deactivated
-    pub fn build_binding_wheels(
-        &self,
-    ) -> Result<Vec<(PathBuf, String, Option<PythonInterpreter>)>, Error> {
+    ///
+    /// With `jobs` > 1, the wheels are built in parallel, see
+    /// [BuildContext::build_binding_wheels_parallel()]
+    pub fn build_binding_wheels(&self) -> Result<Vec<BuiltWheelMetadata>, Error> {
+        // The binaries don't depend on the python version, so they're shared by all wheels
+        let bins = self.compile_bins(self.interpreter.first())?;
+
+        if self.jobs > 1 && self.interpreter.len() > 1 {
+            return self.build_binding_wheels_parallel(&bins);
+        }
+
         let mut wheels = Vec::new();
         for python_interpreter in &self.interpreter {
             let artifact =
                 self.compile_cdylib(Some(&python_interpreter), Some(&self.module_name))?;
+            wheels.push(self.write_binding_wheel(python_interpreter, artifact, &bins)?);
+        }
 
-            let tag = python_interpreter.get_tag(&self.manylinux);
-
-            let mut writer = WheelWriter::new(
-                &tag,
-                &self.out,
-                &self.metadata21,
-                &self.scripts,
-                &[tag.clone()],
-            )?;
-
-            write_bindings_module(
-                &mut writer,
-                &self.project_layout,
-                &self.module_name,
-                &artifact,
-                python_interpreter,
-                false,
-            )
-            .context("Failed to add the files to the wheel")?;
+        Ok(wheels)
+    }
+
+    /// Builds the wheels for up to `jobs` interpreters at the same time. Each interpreter gets
+    /// its own `CARGO_TARGET_DIR` in the target directory, e.g. `target/cpython-3.7m`.
+    ///
+    /// To keep the output readable, the output of cargo is collected and printed as one block
+    /// when the compilation for an interpreter is finished. The audit and the wheel writing for
+    /// that interpreter follow in the same block, while the other interpreters keep compiling.
+    /// After the first failure no new builds are started, and the wheels are returned in the
+    /// order of the interpreters.
+    fn build_binding_wheels_parallel(
+        &self,
+        bins: &[PathBuf],
+    ) -> Result<Vec<BuiltWheelMetadata>, Error> {
+        let target_dirs = self.interpreter_target_dirs();
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let output = Mutex::new(());
+
+        let mut results = Vec::new();
+        thread::scope(|scope| {
+            let (next, failed, output, target_dirs) = (&next, &failed, &output, &target_dirs);
+            let workers: Vec<_> = (0..self.jobs.min(self.interpreter.len()))
+                .map(|_| {
+                    scope.spawn(move || {
+                        let mut results = Vec::new();
+                        loop {
+                            let index = next.fetch_add(1, Ordering::SeqCst);
+                            if index >= self.interpreter.len() || failed.load(Ordering::SeqCst) {
+                                break;
+                            }
+                            let result = self.build_binding_wheel_captured(
+                                &self.interpreter[index],
+                                &target_dirs[index],
+                                output,
+                                bins,
+                            );
+                            if result.is_err() {
+                                failed.store(true, Ordering::SeqCst);
+                            }
+                            results.push((index, result));
+                        }
+                        results
+                    })
+                })
+                .collect();
+            for worker in workers {
+                results.extend(worker.join().expect("Building a wheel panicked"));
+            }
+        });
 
-            let wheel_path = writer.finish()?;
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
 
-            println!(
-                "📦 Built wheel for {} {}.{}{} to {}",
-                python_interpreter.interpreter,
-                python_interpreter.major,
-                python_interpreter.minor,
-                python_interpreter.abiflags,
-                wheel_path.display()
-            );
+    /// Returns a target directory for each interpreter for the parallel builds, which is named
+    /// after the implementation, the version and the abiflags, e.g. `target/pypy-3.6`
+    fn interpreter_target_dirs(&self) -> Vec<PathBuf> {
+        let target_directory = PathBuf::from(&self.cargo_metadata.target_directory);
+        let mut names = HashSet::new();
+        self.interpreter
+            .iter()
+            .enumerate()
+            .map(|(index, python_interpreter)| {
+                let mut name = format!(
+                    "{}-{}.{}{}",
+                    python_interpreter.interpreter.to_string().to_lowercase(),
+                    python_interpreter.major,
+                    python_interpreter.minor,
+                    python_interpreter.abiflags
+                );
+                // Two interpreters with the same version, e.g. from `-i`, must not share a target
+                // directory since they would overwrite each other's artifacts
+                if !names.insert(name.clone()) {
+                    name = format!("{}-{}", name, index);
+                }
+                target_directory.join(name)
+            })
+            .collect()
+    }
 
-            wheels.push((
-                wheel_path,
-                format!("cp{}{}", python_interpreter.major, python_interpreter.minor),
-                Some(python_interpreter.clone()),
-            ));
-        }
+    /// Compiles for one interpreter with the output of cargo captured and prints that output under
+    /// the output lock. The lock is released before the wheel is audited, repaired and written,
+    /// so that this runs concurrently with the other builds
+    fn build_binding_wheel_captured(
+        &self,
+        python_interpreter: &PythonInterpreter,
+        target_dir: &Path,
+        output: &Mutex<()>,
+        bins: &[PathBuf],
+    ) -> Result<BuiltWheelMetadata, Error> {
+        let mut captured = CapturedBuild {
+            target_dir: target_dir.to_path_buf(),
+            log: String::new(),
+        };
+        let artifacts = compile_captured(
+            self,
+            Some(python_interpreter),
+            &self.bridge,
+            Some(&mut captured),
+        );
+
+        let artifact = {
+            // A panic in another build doesn't make the output unusable
+            let _output = output.lock().unwrap_or_else(PoisonError::into_inner);
+            println!("🐍 Building for {}", python_interpreter);
+            print!("{}", captured.log);
+            let artifacts = artifacts.context("Failed to build a native library through cargo")?;
+            self.check_cdylib(artifacts, Some(python_interpreter), Some(&self.module_name))?
+        };
+        self.write_binding_wheel(python_interpreter, artifact, bins)
+    }
 
-        Ok(wheels)
+    /// Audits and repairs the native library for one interpreter and writes it into a wheel
+    /// together with the binaries
+    fn write_binding_wheel(
+        &self,
+        python_interpreter: &PythonInterpreter,
+        artifact: PathBuf,
+        bins: &[PathBuf],
+    ) -> Result<BuiltWheelMetadata, Error> {
+        let manylinux = self.resolve_manylinux(&artifact, &python_interpreter.target)?;
+        let (artifact, bundled_libraries) =
+            self.repair_cdylib(artifact, &python_interpreter.target)?;
+
+        let mut python_interpreter = python_interpreter.clone();
+        let target = self.resolve_target(&artifact, &python_interpreter.target)?;
+        let (manylinux, target) = self.resolve_bins(bins, manylinux, target)?;
+        python_interpreter.target = target;
+        let tag = python_interpreter.get_tag(&manylinux);
+
+        let mut writer = WheelWriter::new(
+            &tag,
+            &self.out,
+            &self.metadata21,
+            &self.scripts,
+            &[tag.clone()],
+        )?;
+
+        write_bindings_module(
+            &mut writer,
+            &self.project_layout,
+            &self.module_name,
+            &artifact,
+            Some(&python_interpreter),
+            &python_interpreter.target,
+            false,
+        )
+        .context("Failed to add the files to the wheel")?;
+        write_bundled_libraries(&mut writer, &self.module_name, &bundled_libraries)?;
+        self.write_bins(&mut writer, bins)?;
+
+        let wheel_path = writer.finish()?;
+
+        println!(
+            "📦 Built wheel for {} {}.{}{} to {}",
+            python_interpreter.interpreter,
+            python_interpreter.major,
+            python_interpreter.minor,
+            python_interpreter.abiflags,
+            wheel_path.display()
+        );
+
+        Ok((
+            wheel_path,
+            format!("cp{}{}", python_interpreter.major, python_interpreter.minor),
+            Some(python_interpreter),
+        ))
+    }
+
+    /// Builds a single wheel with the stable abi (abi3) for all python versions starting with
+    /// the given one, e.g. `cp36-abi3-manylinux1_x86_64`
+    ///
+    /// The crate is compiled only once with the oldest matching CPython interpreter
+    pub fn build_binding_wheel_abi3(
+        &self,
+        major: u8,
+        minor: u8,
+    ) -> Result<BuiltWheelMetadata, Error> {
+        let python_interpreter = self
+            .interpreter
+            .iter()
+            .filter(|interpreter| {
+                interpreter.interpreter == Interpreter::CPython
+                    && (interpreter.major, interpreter.minor) >= (major as usize, minor as usize)
+            })
+            .min_by_key(|interpreter| (interpreter.major, interpreter.minor))
+            .ok_or_else(|| {
+                format_err!(
+                    "Building an abi3 wheel for python {}.{}+ requires a CPython interpreter \
+                     with at least that version",
+                    major,
+                    minor
+                )
+            })?;
+
+        let artifact = self.compile_cdylib(Some(python_interpreter), Some(&self.module_name))?;
+        let bins = self.compile_bins(Some(python_interpreter))?;
+        let manylinux = self.resolve_manylinux(&artifact, &python_interpreter.target)?;
+        let (artifact, bundled_libraries) =
+            self.repair_cdylib(artifact, &python_interpreter.target)?;
+        let target = self.resolve_target(&artifact, &python_interpreter.target)?;
+        let (manylinux, target) = self.resolve_bins(&bins, manylinux, target)?;
+
+        let tag = target.get_abi3_tag(major, minor, &manylinux);
+        let tags = vec![tag.clone()];
+
+        let mut writer = WheelWriter::new(&tag, &self.out, &self.metadata21, &self.scripts, &tags)?;
+
+        write_bindings_module(
+            &mut writer,
+            &self.project_layout,
+            &self.module_name,
+            &artifact,
+            None,
+            &target,
+            false,
+        )
+        .context("Failed to add the files to the wheel")?;
+        write_bundled_libraries(&mut writer, &self.module_name, &bundled_libraries)?;
+        self.write_bins(&mut writer, &bins)?;
+
+        let wheel_path = writer.finish()?;
+
+        println!(
+            "📦 Built abi3 wheel for CPython {}.{}+ to {}",
+            major,
+            minor,
+            wheel_path.display()
+        );
+
+        Ok((wheel_path, format!("cp{}{}", major, minor), None))
     }
 
     /// Runs cargo build, extracts the cdylib from the output, runs auditwheel and returns the
@@ -209,21 +451,49 @@ impl BuildContext {
     ) -> Result<PathBuf, Error> {
         let artifacts = compile(&self, python_interpreter, &self.bridge)
             .context("Failed to build a native library through cargo")?;
+        self.check_cdylib(artifacts, python_interpreter, module_name)
+    }
 
-        let artifact = artifacts.get("cdylib").cloned().ok_or_else(|| {
-            Context::new(
-                "Cargo didn't build a cdylib. Did you miss crate-type = [\"cdylib\"] \
-                 in the lib section of your Cargo.toml?",
-            )
-        })?;
+    /// Extracts the cdylib from the artifacts of cargo and runs auditwheel on it
+    fn check_cdylib(
+        &self,
+        artifacts: HashMap<String, Vec<PathBuf>>,
+        python_interpreter: Option<&PythonInterpreter>,
+        module_name: Option<&str>,
+    ) -> Result<PathBuf, Error> {
+        let artifact = artifacts
+            .get("cdylib")
+            .and_then(|cdylibs| cdylibs.first())
+            .cloned()
+            .ok_or_else(|| {
+                Context::new(
+                    "Cargo didn't build a cdylib. Did you miss crate-type = [\"cdylib\"] \
+                     in the lib section of your Cargo.toml?",
+                )
+            })?;
         #[cfg(feature = "auditwheel")]
         {
             let target = python_interpreter
                 .map(|x| &x.target)
                 .unwrap_or(&self.target);
 
-            auditwheel_rs(&artifact, target, &self.manylinux)
+            if let Some(policy) = self.checked_policy()? {
+                auditwheel_rs(
+                    &artifact,
+                    target,
+                    policy,
+                    self.manylinux.is_repair(),
+                    self.native_library_depth(),
+                )
                 .context("Failed to ensure manylinux compliance")?;
+            }
+
+            if let Some(python_interpreter) = python_interpreter {
+                if python_interpreter.interpreter == Interpreter::PyPy {
+                    audit_pypy(&artifact, target)
+                        .context("The native library isn't usable with PyPy")?;
+                }
+            }
         }
 
         if let Some(module_name) = module_name {
@@ -234,11 +504,210 @@ impl BuildContext {
         Ok(artifact)
     }
 
+    /// Returns the policy the native libraries must comply with, if compliance is checked
+    #[cfg(feature = "auditwheel")]
+    fn checked_policy(&self) -> Result<Option<&Policy>, Error> {
+        match self.manylinux.checked_policy_name() {
+            Some(name) => Ok(Some(Policy::find(&self.policies, name)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Resolves `--manylinux=auto` to the oldest policy the native library complies with and
+    /// reports why the stricter policies were rejected. Falls back to the native linux tag if the
+    /// library complies with no policy. Other values are returned unchanged.
+    fn resolve_manylinux(&self, artifact: &Path, target: &Target) -> Result<Manylinux, Error> {
+        if self.manylinux != Manylinux::Auto {
+            return Ok(self.manylinux.clone());
+        }
+        if !target.is_linux() {
+            return Ok(Manylinux::Off);
+        }
+
+        #[cfg(feature = "auditwheel")]
+        {
+            let audit = find_best_policy(
+                artifact,
+                target,
+                &self.policies,
+                self.native_library_depth(),
+            )
+            .context("Failed to check manylinux compliance")?;
+            for (policy, reason) in &audit.rejected {
+                println!("🔍 Not using {}: {}", policy.name, reason);
+            }
+            match audit.policy {
+                Some(policy) => {
+                    println!("🔍 Using {}, the oldest compatible policy", policy.name);
+                    Ok(Manylinux::Checked(policy.name.clone()))
+                }
+                None => {
+                    eprintln!(
+                        "⚠ Your library doesn't comply with any manylinux policy, \
+                         so the native linux tag is used"
+                    );
+                    Ok(Manylinux::Off)
+                }
+            }
+        }
+        #[cfg(not(feature = "auditwheel"))]
+        {
+            let _ = artifact;
+            bail!("--manylinux=auto requires pyo3-pack to be built with the auditwheel feature")
+        }
+    }
+
+    /// Builds the binaries in [BuildContext::bins] and runs [auditwheel_rs()] on them if not
+    /// deactivated. For bindings, those are the extra binaries that are put into the wheel next
+    /// to the native module, so they're built only once with the interpreter of the first wheel
+    fn compile_bins(
+        &self,
+        python_interpreter: Option<&PythonInterpreter>,
+    ) -> Result<Vec<PathBuf>, Error> {
+        if self.bins.is_empty() {
+            return Ok(Vec::new());
+        }
+        let artifacts = compile(&self, python_interpreter, &BridgeModel::Bin)
+            .context("Failed to build the binaries through cargo")?
+            .remove("bin")
+            .unwrap_or_default();
+
+        #[cfg(feature = "auditwheel")]
+        {
+            if let Some(policy) = self.checked_policy()? {
+                for artifact in &artifacts {
+                    // <name>.data/scripts/<binary>
+                    auditwheel_rs(artifact, &self.target, policy, false, 2).context(format!(
+                        "Failed to ensure manylinux compliance of {}",
+                        artifact.display()
+                    ))?;
+                }
+            }
+        }
+
+        Ok(artifacts)
+    }
+
+    /// Widens the manylinux policy and the target of a wheel to also cover the binaries in it,
+    /// i.e. the least strict policy and the newest mac os version win
+    fn resolve_bins(
+        &self,
+        bins: &[PathBuf],
+        mut manylinux: Manylinux,
+        mut target: Target,
+    ) -> Result<(Manylinux, Target), Error> {
+        for bin in bins {
+            let bin_manylinux = self.resolve_manylinux(bin, &target)?;
+            manylinux = self.least_strict_manylinux(manylinux, bin_manylinux);
+            let bin_target = self.resolve_target(bin, &target)?;
+            if newer_os_release(&bin_target, &target) {
+                target = bin_target;
+            }
+        }
+        Ok((manylinux, target))
+    }
+
+    /// Adds the binaries to the `.data/scripts` directory of the wheel
+    fn write_bins(&self, writer: &mut WheelWriter, bins: &[PathBuf]) -> Result<(), Error> {
+        for bin in bins {
+            // I wouldn't know of any case where this would be the wrong (and neither do
+            // I know a better alternative)
+            let bin_name = bin
+                .file_name()
+                .expect("Couldn't get the filename from the binary produced by cargo");
+            write_bin(writer, bin, &self.metadata21, bin_name)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the policy of the two that is less strict, where the native linux tag is the least
+    /// strict of all, so that a wheel with several binaries complies with its tag
+    fn least_strict_manylinux(&self, left: Manylinux, right: Manylinux) -> Manylinux {
+        if left == Manylinux::Off || right == Manylinux::Off {
+            return Manylinux::Off;
+        }
+        let priority = |manylinux: &Manylinux| {
+            manylinux
+                .checked_policy_name()
+                .and_then(|name| Policy::find(&self.policies, name).ok())
+                .map(|policy| policy.priority)
+        };
+        match (priority(&left), priority(&right)) {
+            (Some(left_priority), Some(right_priority)) if right_priority < left_priority => right,
+            _ => left,
+        }
+    }
+
+    /// Adds the mac os version the artifact was built for to the target, since it's part of the
+    /// platform tag
+    fn resolve_target(&self, artifact: &Path, target: &Target) -> Result<Target, Error> {
+        if !target.is_macos() {
+            return Ok(target.clone());
+        }
+        let version = get_macos_version(artifact)
+            .context("Failed to read the mac os version from the native library")?;
+        Ok(match version {
+            Some(version) => target.with_os_release(&version),
+            None => target.clone(),
+        })
+    }
+
+    /// Returns the number of directories between the native library or the binary and the root of
+    /// the wheel
+    fn native_library_depth(&self) -> usize {
+        match (&self.bridge, &self.project_layout) {
+            (BridgeModel::Bindings(_), ProjectLayout::Mixed(_))
+            | (BridgeModel::BindingsAbi3(_, _), ProjectLayout::Mixed(_)) => 1,
+            (BridgeModel::Bindings(_), ProjectLayout::PureRust)
+            | (BridgeModel::BindingsAbi3(_, _), ProjectLayout::PureRust) => 0,
+            (BridgeModel::Cffi, ProjectLayout::Mixed(_)) => 2,
+            (BridgeModel::Cffi, ProjectLayout::PureRust) => 1,
+            // <name>.data/scripts/<binary>
+            (BridgeModel::Bin, _) => 2,
+        }
+    }
+
+    /// With `--manylinux=<policy>-repair`, bundles the libraries that the policy doesn't
+    /// whitelist and returns the patched native library together with the bundled libraries.
+    /// Otherwise the native library is returned unchanged.
+    ///
+    /// The bundled libraries are put in `<module name>.libs` at the root of the wheel.
+    fn repair_cdylib(
+        &self,
+        artifact: PathBuf,
+        target: &Target,
+    ) -> Result<(PathBuf, BundledLibraries), Error> {
+        #[cfg(feature = "auditwheel")]
+        {
+            let runpath = format!(
+                "$ORIGIN/{}{}.libs",
+                "../".repeat(self.native_library_depth()),
+                self.module_name
+            );
+            if let (true, Some(policy)) = (self.manylinux.is_repair(), self.checked_policy()?) {
+                if let Some(repaired) = repair(&artifact, target, policy, &runpath)
+                    .context("Failed to bundle the external libraries")?
+                {
+                    return Ok((repaired.artifact, repaired.libraries));
+                }
+            }
+        }
+        #[cfg(not(feature = "auditwheel"))]
+        let _ = target;
+
+        Ok((artifact, Vec::new()))
+    }
+
     /// Builds a wheel with cffi bindings
     pub fn build_cffi_wheel(&self) -> Result<PathBuf, Error> {
         let artifact = self.compile_cdylib(None, None)?;
+        let bins = self.compile_bins(None)?;
+        let manylinux = self.resolve_manylinux(&artifact, &self.target)?;
+        let (artifact, bundled_libraries) = self.repair_cdylib(artifact, &self.target)?;
 
-        let (tag, tags) = self.target.get_universal_tags(&self.manylinux);
+        let target = self.resolve_target(&artifact, &self.target)?;
+        let (manylinux, target) = self.resolve_bins(&bins, manylinux, target)?;
+        let (tag, tags) = target.get_universal_tags(&manylinux);
 
         let mut builder =
             WheelWriter::new(&tag, &self.out, &self.metadata21, &self.scripts, &tags)?;
@@ -252,6 +721,8 @@ impl BuildContext {
             &self.interpreter[0].executable,
             false,
         )?;
+        write_bundled_libraries(&mut builder, &self.module_name, &bundled_libraries)?;
+        self.write_bins(&mut builder, &bins)?;
 
         let wheel_path = builder.finish()?;
 
@@ -260,23 +731,23 @@ impl BuildContext {
         Ok(wheel_path)
     }
 
-    /// Builds a wheel that contains a binary
+    /// Builds a wheel that contains the binaries in the `.data/scripts` directory
     ///
     /// Runs [auditwheel_rs()] if not deactivated
     pub fn build_bin_wheel(&self) -> Result<PathBuf, Error> {
-        let artifacts = compile(&self, None, &self.bridge)
-            .context("Failed to build a native library through cargo")?;
-
-        let artifact = artifacts
-            .get("bin")
-            .cloned()
-            .ok_or_else(|| Context::new("Cargo didn't build a binary."))?;
+        let artifacts = self.compile_bins(None)?;
+        if artifacts.is_empty() {
+            bail!("Cargo didn't build a binary.");
+        }
 
-        #[cfg(feature = "auditwheel")]
-        auditwheel_rs(&artifact, &self.target, &self.manylinux)
-            .context("Failed to ensure manylinux compliance")?;
+        if self.manylinux.is_repair() {
+            bail!("Bundling shared libraries is only supported for native python modules");
+        }
 
-        let (tag, tags) = self.target.get_universal_tags(&self.manylinux);
+        let manylinux = self.resolve_manylinux(&artifacts[0], &self.target)?;
+        let target = self.resolve_target(&artifacts[0], &self.target)?;
+        let (manylinux, target) = self.resolve_bins(&artifacts[1..], manylinux, target)?;
+        let (tag, tags) = target.get_universal_tags(&manylinux);
 
         if !self.scripts.is_empty() {
             bail!("Defining entrypoints and working with a binary doesn't mix well");
@@ -293,12 +764,7 @@ impl BuildContext {
             ProjectLayout::PureRust => {}
         }
 
-        // I wouldn't know of any case where this would be the wrong (and neither do
-        // I know a better alternative)
-        let bin_name = artifact
-            .file_name()
-            .expect("Couldn't get the filename from the binary produced by cargo");
-        write_bin(&mut builder, &artifact, &self.metadata21, bin_name)?;
+        self.write_bins(&mut builder, &artifacts)?;
 
         let wheel_path = builder.finish()?;
 
@@ -307,3 +773,16 @@ impl BuildContext {
         Ok(wheel_path)
     }
 }
+
+/// Returns whether the mac os version of the first target is newer than the one of the second
+fn newer_os_release(target: &Target, other: &Target) -> bool {
+    let parse = |target: &Target| -> Vec<u64> {
+        target
+            .os_release()
+            .unwrap_or_default()
+            .split('.')
+            .filter_map(|part| part.parse().ok())
+            .collect()
+    };
+    parse(target) > parse(other)
+}
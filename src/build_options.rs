@@ -1,15 +1,20 @@
 use crate::build_context::{BridgeModel, ProjectLayout};
+use crate::pep440::VersionSpecifiers;
+use crate::python_interpreter::Interpreter;
 use crate::BuildContext;
 use crate::CargoToml;
 use crate::Manylinux;
 use crate::Metadata21;
+use crate::Policy;
 use crate::PythonInterpreter;
 use crate::Target;
-use cargo_metadata::{Metadata, MetadataCommand, Node};
+use cargo_metadata::{Metadata, MetadataCommand, Node, Package, PackageId};
 use failure::{bail, err_msg, format_err, Error, ResultExt};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::iter;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 
 /// High level API for building wheels from a crate which is also used for the CLI
@@ -21,27 +26,46 @@ pub struct BuildOptions {
     ///
     /// - `1`: Use the manylinux1 tag and check for compliance{n}
     /// - `1-unchecked`: Use the manylinux1 tag without checking for compliance{n}
+    /// - `1-repair`: Use the manylinux1 tag and bundle the libraries that aren't whitelisted into the wheel{n}
     /// - `2010`: Use the manylinux2010 tag and check for compliance{n}
     /// - `2010-unchecked`: Use the manylinux1 tag without checking for compliance{n}
+    /// - `2010-repair`: Use the manylinux2010 tag and bundle the libraries that aren't whitelisted into the wheel{n}
+    /// - `2014`: Use the manylinux2014 tag and check for compliance{n}
+    /// - `2014-unchecked`: Use the manylinux2014 tag without checking for compliance{n}
+    /// - `2014-repair`: Use the manylinux2014 tag and bundle the libraries that aren't whitelisted into the wheel{n}
+    /// - `musllinux_1_1`: Use the musllinux_1_1 tag and check for compliance{n}
+    /// - `musllinux_1_1-unchecked`: Use the musllinux_1_1 tag without checking for compliance{n}
+    /// - `musllinux_1_1-repair`: Use the musllinux_1_1 tag and bundle the libraries that aren't whitelisted into the wheel{n}
+    /// - `auto`: Use the tag of the oldest manylinux policy the library complies with{n}
     /// - `off`: Use the native linux tag (off)
     ///
-    /// This option is ignored on all non-linux platforms
-    #[structopt(
-        long,
-        raw(
-            possible_values = r#"&["1", "1-unchecked", "2010", "2010-unchecked", "off"]"#,
-            case_insensitive = "true",
-            default_value = r#""1""#
-        )
-    )]
+    /// The name of any other policy from `--manylinux-policy` can be used in the same way, e.g.
+    /// `manylinux_2_24-repair`. This option is ignored on all non-linux platforms. For musl
+    /// targets, the manylinux values use musllinux_1_1 instead
+    #[structopt(long, raw(default_value = r#""1""#))]
     pub manylinux: Manylinux,
+    /// A policy file in the format of auditwheel's policy.json, which replaces the embedded
+    /// manylinux policies
+    #[structopt(long = "manylinux-policy", parse(from_os_str))]
+    pub manylinux_policy: Option<PathBuf>,
     #[structopt(short, long)]
     /// The python versions to build wheels for, given as the names of the
-    /// interpreters. Uses autodiscovery if not explicitly set.
+    /// interpreters or as versions such as `3.7` or `pypy3.6`, which are looked
+    /// up in the discovered interpreters. Uses autodiscovery if not explicitly set.
     pub interpreter: Vec<PathBuf>,
+    /// Sysconfig files of python installations for another target, which are used instead of
+    /// running an interpreter when cross compiling. Both the output of `python -m sysconfig` and
+    /// the `_sysconfigdata_*.py` files from the target's `lib/python3.X` are supported
+    #[structopt(long = "interpreter-sysconfig", parse(from_os_str))]
+    pub interpreter_sysconfig: Vec<PathBuf>,
     /// Which kind of bindings to use. Possible values are pyo3, rust-cpython, cffi and bin
     #[structopt(short, long)]
     pub bindings: Option<String>,
+    /// The binaries to put into the wheel. They can also be added to wheels with bindings, and be
+    /// taken from other members of the workspace. Defaults to `bins` in
+    /// `[package.metadata.pyo3-pack]`, or with `-b bin` to all binaries of the package
+    #[structopt(long = "bin", name = "BIN")]
+    pub bins: Vec<String>,
     #[structopt(
         short = "m",
         long = "manifest-path",
@@ -51,6 +75,10 @@ pub struct BuildOptions {
     )]
     /// The path to the Cargo.toml
     pub manifest_path: PathBuf,
+    /// The package to build out of the members of the workspace. Defaults to the package of the
+    /// Cargo.toml given with --manifest-path
+    #[structopt(long = "package")]
+    pub package: Option<String>,
     /// The directory to store the built wheels in. Defaults to a new "wheels"
     /// directory in the project's target directory
     #[structopt(short, long, parse(from_os_str))]
@@ -61,6 +89,10 @@ pub struct BuildOptions {
     /// The --target option for cargo
     #[structopt(long, name = "TRIPLE")]
     pub target: Option<String>,
+    /// Build a universal2 wheel for mac os, which contains the native library for both x86_64
+    /// and arm64
+    #[structopt(long)]
+    pub universal2: bool,
     /// Extra arguments that will be passed to cargo as `cargo rustc [...] [arg1] [arg2] --`
     ///
     /// Use as `--cargo-extra-args="--my-arg"`
@@ -71,20 +103,31 @@ pub struct BuildOptions {
     /// Use as `--rustc-extra-args="--my-arg"`
     #[structopt(long = "rustc-extra-args")]
     pub rustc_extra_args: Vec<String>,
+    /// The number of python interpreters to build wheels for at the same time. Each interpreter
+    /// gets its own target directory, e.g. `target/cpython-3.7m`, and the output is printed
+    /// per interpreter
+    #[structopt(short = "j", long = "jobs", default_value = "1")]
+    pub jobs: usize,
 }
 
 impl Default for BuildOptions {
     fn default() -> Self {
         BuildOptions {
-            manylinux: Manylinux::Manylinux1,
+            manylinux: Manylinux::Checked("manylinux1".to_string()),
+            manylinux_policy: None,
             interpreter: vec![],
+            interpreter_sysconfig: vec![],
             bindings: None,
+            bins: Vec::new(),
             manifest_path: PathBuf::from("Cargo.toml"),
+            package: None,
             out: None,
             skip_auditwheel: false,
             target: None,
+            universal2: false,
             cargo_extra_args: Vec::new(),
             rustc_extra_args: Vec::new(),
+            jobs: 1,
         }
     }
 }
@@ -105,9 +148,20 @@ impl BuildOptions {
             );
         };
 
+        // Failure fails here since cargo_metadata does some weird stuff on their side
+        let cargo_metadata = MetadataCommand::new()
+            .manifest_path(&self.manifest_path)
+            .exec()
+            .map_err(|e| format_err!("Cargo metadata failed: {}", e))?;
+
+        // In a workspace, everything is read from the manifest of the selected member
+        let package = select_package(&cargo_metadata, &manifest_file, self.package.as_deref())?;
+        let package_id = package.id.clone();
+        let manifest_file = package.manifest_path.clone();
+
         let cargo_toml = CargoToml::from_path(&manifest_file)?;
         let manifest_dir = manifest_file.parent().unwrap();
-        let metadata21 = Metadata21::from_cargo_toml(&cargo_toml, &manifest_dir)
+        let mut metadata21 = Metadata21::from_cargo_toml(&cargo_toml, &manifest_dir)
             .context("Failed to parse Cargo.toml into python metadata")?;
         let scripts = cargo_toml.scripts();
 
@@ -123,19 +177,35 @@ impl BuildOptions {
         let project_layout = ProjectLayout::determine(manifest_dir, &module_name)?;
 
         let target = Target::from_target_triple(self.target.clone())?;
-
-        // Failure fails here since cargo_metadata does some weird stuff on their side
-        let cargo_metadata = MetadataCommand::new()
-            .manifest_path(&self.manifest_path)
-            .exec()
-            .map_err(|e| format_err!("Cargo metadata failed: {}", e))?;
+        let target = if self.universal2 {
+            if self.target.is_some() {
+                bail!(
+                    "--universal2 builds for both mac os targets and can't be used with --target"
+                );
+            }
+            target.with_universal2()?
+        } else {
+            target
+        };
 
         let wheel_dir = match self.out {
             Some(ref dir) => dir.clone(),
             None => PathBuf::from(&cargo_metadata.target_directory).join("wheels"),
         };
 
-        let bridge = find_bridge(&cargo_metadata, self.bindings.as_ref().map(|x| &**x))?;
+        let bridge = find_bridge(
+            &cargo_metadata,
+            &package_id,
+            self.bindings.as_ref().map(|x| &**x),
+        )?;
+
+        let bins = select_bins(
+            &cargo_metadata,
+            &package_id,
+            &bridge,
+            &self.bins,
+            cargo_toml.bins(),
+        )?;
 
         if bridge != BridgeModel::Bin && module_name.contains('-') {
             bail!(
@@ -144,7 +214,40 @@ impl BuildOptions {
             );
         }
 
-        let interpreter = find_interpreter(&bridge, &self.interpreter, &target)?;
+        let requires_python = match metadata21.requires_python {
+            Some(ref requires_python) => Some(
+                requires_python
+                    .parse::<VersionSpecifiers>()
+                    .context(format!(
+                        "requires-python = \"{}\" is not a valid version specifier",
+                        requires_python
+                    ))?,
+            ),
+            None => None,
+        };
+
+        let interpreter = find_interpreter(
+            &bridge,
+            &self.interpreter,
+            &self.interpreter_sysconfig,
+            requires_python.as_ref(),
+            &target,
+        )?;
+        let bridge = resolve_abi3_bridge(bridge, &cargo_metadata, &package_id, &interpreter)?;
+
+        // When cross compiling for a bsd, its release is only known from the interpreter's sysconfig
+        let target = if target.is_bsd() && target.os_release().is_none() {
+            match interpreter.iter().find(|x| x.target.os_release().is_some()) {
+                Some(interpreter) => interpreter.target.clone(),
+                None => bail!(
+                    "The release of the target system is part of the platform tag for the bsds, \
+                     but it couldn't be determined. Please build on the target system or pass an \
+                     interpreter with -i or --interpreter-sysconfig"
+                ),
+            }
+        } else {
+            target
+        };
 
         let mut cargo_extra_args = split_extra_args(&self.cargo_extra_args)?;
         if let Some(target) = self.target {
@@ -153,13 +256,81 @@ impl BuildOptions {
 
         let rustc_extra_args = split_extra_args(&self.rustc_extra_args)?;
 
+        // An extra only makes sense if the rust functionality it's for is part of the wheel
+        let features = cargo_toml.features();
+        let enabled_features = enabled_features(&package.features, &features, &cargo_extra_args);
+        for (feature, requirements) in cargo_toml.extras() {
+            if !package.features.contains_key(&feature) {
+                let mut known: Vec<&str> = package.features.keys().map(String::as_str).collect();
+                known.sort();
+                bail!(
+                    "The extra {} must be named after a feature of {}, the features are: {}",
+                    feature,
+                    package.name,
+                    known.join(", ")
+                );
+            }
+            if enabled_features.contains(&feature) {
+                metadata21.add_extra(&feature, &requirements);
+            }
+        }
+
+        if self.jobs == 0 {
+            bail!("--jobs must be at least 1");
+        }
+        if self.jobs > 1
+            && cargo_extra_args
+                .iter()
+                .any(|arg| arg == "--target-dir" || arg.starts_with("--target-dir="))
+        {
+            bail!(
+                "--jobs can't be used with --target-dir in the cargo extra args, since every \
+                 interpreter needs its own target directory"
+            );
+        }
+
         let manylinux = if self.skip_auditwheel {
             eprintln!("⚠ --skip-auditwheel is deprecated, use --manylinux=1-unchecked");
-            Manylinux::Manylinux1Unchecked
+            Manylinux::Unchecked("manylinux1".to_string())
         } else {
             self.manylinux
         };
 
+        // The manylinux policies are for glibc, so musl targets use musllinux instead
+        let manylinux = match manylinux.policy_name() {
+            Some(name) if target.is_musl() && !name.starts_with("musllinux") => {
+                manylinux.with_policy("musllinux_1_1")
+            }
+            Some(name)
+                if target.is_linux() && !target.is_musl() && name.starts_with("musllinux") =>
+            {
+                bail!("{} can only be used for musl targets", name)
+            }
+            _ => manylinux,
+        };
+
+        let policies = match self.manylinux_policy {
+            Some(ref path) => Policy::from_file(path)?,
+            None => Policy::embedded(),
+        };
+        if let Some(name) = manylinux.policy_name() {
+            let policy = Policy::find(&policies, name)?;
+            let arch = target.get_linux_arch();
+            if target.is_linux() && !policy.supports_arch(arch) {
+                let supported: Vec<&str> = policies
+                    .iter()
+                    .filter(|policy| policy.supports_arch(arch))
+                    .map(|policy| policy.name.as_str())
+                    .collect();
+                bail!(
+                    "{} doesn't support {}, the policies supporting it are: {}",
+                    name,
+                    arch,
+                    supported.join(", ")
+                );
+            }
+        }
+
         Ok(BuildContext {
             target,
             bridge,
@@ -167,30 +338,292 @@ impl BuildOptions {
             metadata21,
             scripts,
             module_name,
-            manifest_path: self.manifest_path,
+            manifest_path: manifest_file,
+            package_id,
+            bins,
+            features,
             out: wheel_dir,
             release,
             strip,
             manylinux,
+            policies,
             cargo_extra_args,
             rustc_extra_args,
             interpreter,
             cargo_metadata,
+            jobs: self.jobs,
         })
     }
 }
 
-/// Tries to determine the [BridgeModel] for the target crate
-pub fn find_bridge(cargo_metadata: &Metadata, bridge: Option<&str>) -> Result<BridgeModel, Error> {
-    let nodes = cargo_metadata
+/// The minimum python version of an abi3 wheel as given by pyo3's features
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Abi3Version {
+    /// The lowest `abi3-pyXY` feature, e.g. `(3, 6)` for `abi3-py36`
+    Version(u8, u8),
+    /// Only the bare `abi3` feature, so the lowest selected interpreter is the minimum
+    CurrentPython,
+}
+
+/// Returns the minimum python version of pyo3's `abi3-pyXY` features, or
+/// [Abi3Version::CurrentPython] for the bare `abi3` feature, or `None` if abi3 isn't enabled
+fn find_abi3_version(features: &[String]) -> Option<Abi3Version> {
+    let version = features
+        .iter()
+        .filter_map(|feature| {
+            let version = feature.trim_start_matches("abi3-py");
+            if version.len() < 2 || version.len() == feature.len() {
+                return None;
+            }
+            let (major, minor) = version.split_at(1);
+            Some((major.parse().ok()?, minor.parse().ok()?))
+        })
+        .min();
+    match version {
+        Some((major, minor)) => Some(Abi3Version::Version(major, minor)),
+        None if features.iter().any(|feature| feature == "abi3") => {
+            Some(Abi3Version::CurrentPython)
+        }
+        None => None,
+    }
+}
+
+/// Returns the version of the lowest CPython interpreter, which is the minimum python version of
+/// an abi3 wheel with pyo3's bare `abi3` feature
+fn lowest_abi3_version(interpreter: &[PythonInterpreter]) -> Result<(u8, u8), Error> {
+    interpreter
+        .iter()
+        .filter(|interpreter| interpreter.interpreter == Interpreter::CPython)
+        .map(|interpreter| (interpreter.major as u8, interpreter.minor as u8))
+        .min()
+        .ok_or_else(|| format_err!("Building an abi3 wheel requires a CPython interpreter"))
+}
+
+/// Turns the `Bindings` that [find_bridge] returns for pyo3's bare `abi3` feature into
+/// `BindingsAbi3` with the lowest selected interpreter as minimum, e.g. `cp36-abi3` if python 3.6
+/// is the lowest one
+fn resolve_abi3_bridge(
+    bridge: BridgeModel,
+    cargo_metadata: &Metadata,
+    package_id: &PackageId,
+    interpreter: &[PythonInterpreter],
+) -> Result<BridgeModel, Error> {
+    if bridge != BridgeModel::Bindings("pyo3".to_string()) {
+        return Ok(bridge);
+    }
+    let deps = find_dependencies(cargo_metadata, package_id)?;
+    match deps
+        .get("pyo3")
+        .and_then(|node| find_abi3_version(&node.features))
+    {
+        Some(Abi3Version::CurrentPython) => {
+            let (major, minor) = lowest_abi3_version(interpreter)?;
+            Ok(BridgeModel::BindingsAbi3(major, minor))
+        }
+        _ => Ok(bridge),
+    }
+}
+
+/// Returns the features of the package that are enabled for the build, given the features of the
+/// package from cargo metadata: The default features unless `--no-default-features` is in the
+/// cargo extra args, `features` from `[package.metadata.pyo3-pack]`, the ones from `--features`
+/// or `--all-features` in the cargo extra args and the features that those enable in turn
+fn enabled_features(
+    package_features: &HashMap<String, Vec<String>>,
+    features: &[String],
+    cargo_extra_args: &[String],
+) -> HashSet<String> {
+    let mut requested: Vec<String> = features.to_vec();
+    let mut default_features = true;
+    let mut args = cargo_extra_args.iter();
+    while let Some(arg) = args.next() {
+        let value = match arg.as_str() {
+            "--features" => args.next().map(String::as_str),
+            "--all-features" => {
+                requested.extend(package_features.keys().cloned());
+                None
+            }
+            "--no-default-features" => {
+                default_features = false;
+                None
+            }
+            _ => arg.strip_prefix("--features="),
+        };
+        if let Some(value) = value {
+            requested.extend(
+                value
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|feature| !feature.is_empty())
+                    .map(ToString::to_string),
+            );
+        }
+    }
+    if default_features {
+        requested.push("default".to_string());
+    }
+
+    let mut enabled = HashSet::new();
+    while let Some(feature) = requested.pop() {
+        // Features of dependencies such as `pyo3/nightly` don't enable features of the package
+        if feature.contains('/') || !enabled.insert(feature.clone()) {
+            continue;
+        }
+        if let Some(implied) = package_features.get(&feature) {
+            requested.extend(implied.iter().cloned());
+        }
+    }
+    enabled
+}
+
+/// Returns the names of the bin targets of a package
+fn bin_names(package: &Package) -> impl Iterator<Item = &str> {
+    package
+        .targets
+        .iter()
+        .filter(|target| target.kind.iter().any(|kind| kind == "bin"))
+        .map(|target| target.name.as_str())
+}
+
+/// Returns the package that contains the binary, which is the selected package or another member
+/// of the workspace
+pub fn find_bin_package<'a>(
+    cargo_metadata: &'a Metadata,
+    package_id: &PackageId,
+    bin: &str,
+) -> Option<&'a Package> {
+    let members = cargo_metadata
+        .workspace_members
+        .iter()
+        .map(|member| &cargo_metadata[member]);
+    iter::once(&cargo_metadata[package_id])
+        .chain(members)
+        .find(|package| bin_names(package).any(|name| name == bin))
+}
+
+/// Returns the binaries to put into the wheel, which are the ones given with `--bin`, otherwise
+/// `bins` from `[package.metadata.pyo3-pack]`. Otherwise, those are all binaries of the package
+/// for `-b bin` and none for bindings and cffi, which get the binaries in addition to the native
+/// module.
+///
+/// The binaries can also be taken from other members of the workspace
+pub fn select_bins(
+    cargo_metadata: &Metadata,
+    package_id: &PackageId,
+    bridge: &BridgeModel,
+    bins: &[String],
+    metadata_bins: Option<Vec<String>>,
+) -> Result<Vec<String>, Error> {
+    let package = &cargo_metadata[package_id];
+    let selected = if !bins.is_empty() {
+        bins.to_vec()
+    } else if let Some(metadata_bins) = metadata_bins {
+        metadata_bins
+    } else if *bridge == BridgeModel::Bin {
+        bin_names(package).map(ToString::to_string).collect()
+    } else {
+        Vec::new()
+    };
+    if let Some(unknown) = selected
+        .iter()
+        .find(|bin| find_bin_package(cargo_metadata, package_id, bin).is_none())
+    {
+        let available: Vec<&str> = cargo_metadata
+            .workspace_members
+            .iter()
+            .flat_map(|member| bin_names(&cargo_metadata[member]))
+            .collect();
+        bail!(
+            "Neither {} nor the other members of its workspace have a binary called {}, \
+             the binaries are: {}",
+            package.name,
+            unknown,
+            available.join(", ")
+        );
+    }
+    if *bridge == BridgeModel::Bin && selected.is_empty() {
+        bail!("{} has no binaries to put into the wheel", package.name);
+    }
+    Ok(selected)
+}
+
+/// Returns the workspace member given with `--package`, or otherwise the one whose Cargo.toml was
+/// passed with `--manifest-path`, which must then not be a virtual manifest
+pub fn select_package<'a>(
+    cargo_metadata: &'a Metadata,
+    manifest_file: &Path,
+    package: Option<&str>,
+) -> Result<&'a Package, Error> {
+    let members: Vec<&Package> = cargo_metadata
+        .workspace_members
+        .iter()
+        .map(|id| &cargo_metadata[id])
+        .collect();
+    let member_names = || {
+        let names: Vec<&str> = members.iter().map(|member| member.name.as_str()).collect();
+        names.join(", ")
+    };
+
+    let selected = match package {
+        Some(name) => members.iter().find(|member| member.name == name),
+        None => members.iter().find(|member| {
+            fs::canonicalize(&member.manifest_path).ok().as_deref() == Some(manifest_file)
+        }),
+    };
+    match (selected, package) {
+        (Some(member), _) => Ok(member),
+        (None, Some(name)) => bail!(
+            "The package {} is not a member of the workspace, the members are: {}",
+            name,
+            member_names()
+        ),
+        (None, None) => bail!(
+            "{} is the manifest of a workspace, please select one of its members with --package: {}",
+            manifest_file.display(),
+            member_names()
+        ),
+    }
+}
+
+/// Returns the resolved dependencies of the package by name, including the package itself
+fn find_dependencies(
+    cargo_metadata: &Metadata,
+    package_id: &PackageId,
+) -> Result<HashMap<String, Node>, Error> {
+    let nodes: HashMap<&PackageId, &Node> = cargo_metadata
         .resolve
-        .clone()
+        .as_ref()
         .ok_or_else(|| format_err!("Expected to get a dependency graph from cargo"))?
-        .nodes;
-    let deps: HashMap<String, Node> = nodes
+        .nodes
         .iter()
-        .map(|node| (cargo_metadata[&node.id].name.clone(), node.clone()))
+        .map(|node| (&node.id, node))
         .collect();
+    let mut deps: HashMap<String, Node> = HashMap::new();
+    let mut visited = HashSet::new();
+    let mut queue = vec![package_id];
+    while let Some(id) = queue.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        if let Some(node) = nodes.get(id) {
+            deps.insert(cargo_metadata[id].name.clone(), (*node).clone());
+            queue.extend(&node.dependencies);
+        }
+    }
+    Ok(deps)
+}
+
+/// Tries to determine the [BridgeModel] for the target crate
+///
+/// Only the dependencies of the selected package are considered, so that other members of the
+/// workspace don't matter. With only pyo3's bare `abi3` feature, the minimum python version
+/// depends on the interpreters, so this returns `Bindings` and [resolve_abi3_bridge] turns it
+/// into `BindingsAbi3` once they are known.
+pub fn find_bridge(
+    cargo_metadata: &Metadata,
+    package_id: &PackageId,
+    bridge: Option<&str>,
+) -> Result<BridgeModel, Error> {
+    let deps = find_dependencies(cargo_metadata, package_id)?;
 
     if let Some(bindings) = bridge {
         if bindings == "cffi" {
@@ -205,10 +638,28 @@ pub fn find_bridge(cargo_metadata: &Metadata, bridge: Option<&str>) -> Result<Br
                 );
             }
 
+            if bindings == "pyo3" {
+                if let Some(Abi3Version::Version(major, minor)) =
+                    find_abi3_version(&deps[bindings].features)
+                {
+                    return Ok(BridgeModel::BindingsAbi3(major, minor));
+                }
+            }
+
             Ok(BridgeModel::Bindings(bindings.to_string()))
         }
     } else if let Some(node) = deps.get("pyo3") {
-        println!("🔗 Found pyo3 bindings");
+        let abi3_version = find_abi3_version(&node.features);
+        match abi3_version {
+            Some(Abi3Version::Version(major, minor)) => println!(
+                "🔗 Found pyo3 bindings with abi3 support for python {}.{}+",
+                major, minor
+            ),
+            Some(Abi3Version::CurrentPython) => {
+                println!("🔗 Found pyo3 bindings with abi3 support")
+            }
+            None => println!("🔗 Found pyo3 bindings"),
+        }
         if !node.features.contains(&"extension-module".to_string()) {
             let version = cargo_metadata[&node.id].version.to_string();
             println!(
@@ -218,7 +669,10 @@ pub fn find_bridge(cargo_metadata: &Metadata, bridge: Option<&str>) -> Result<Br
                 version
             );
         }
-        Ok(BridgeModel::Bindings("pyo3".to_string()))
+        match abi3_version {
+            Some(Abi3Version::Version(major, minor)) => Ok(BridgeModel::BindingsAbi3(major, minor)),
+            _ => Ok(BridgeModel::Bindings("pyo3".to_string())),
+        }
     } else if deps.contains_key("cpython") {
         println!("🔗 Found rust-cpython bindings");
         Ok(BridgeModel::Bindings("rust_cpython".to_string()))
@@ -229,21 +683,64 @@ pub fn find_bridge(cargo_metadata: &Metadata, bridge: Option<&str>) -> Result<Br
 
 /// Finds the appropriate amount for python versions for each [BridgeModel].
 ///
-/// This means all for bindings, one for cffi and zero for bin.
+/// This means all for bindings, one for cffi and zero for bin. The interpreters from sysconfig
+/// files are used in addition to the ones given with -i, and disable the autodiscovery.
+///
+/// For bindings, discovered interpreters that don't match requires-python are skipped, while
+/// given ones are an error.
 pub fn find_interpreter(
     bridge: &BridgeModel,
     interpreter: &[PathBuf],
+    interpreter_sysconfig: &[PathBuf],
+    requires_python: Option<&VersionSpecifiers>,
     target: &Target,
 ) -> Result<Vec<PythonInterpreter>, Error> {
     Ok(match bridge {
-        BridgeModel::Bindings(_) => {
-            let interpreter = if !interpreter.is_empty() {
-                PythonInterpreter::check_executables(&interpreter, &target)
+        BridgeModel::Bindings(_) | BridgeModel::BindingsAbi3(_, _) => {
+            let mut interpreter = if !interpreter.is_empty() {
+                PythonInterpreter::find_selected(&interpreter, &target)
                     .context("The given list of python interpreters is invalid")?
+            } else if interpreter_sysconfig.is_empty() {
+                let found = PythonInterpreter::find_all(&target)
+                    .context("Finding python interpreters failed")?;
+                match requires_python {
+                    Some(requires_python) => found
+                        .into_iter()
+                        .filter(|interpreter| {
+                            let matches = requires_python.contains(
+                                interpreter.major,
+                                interpreter.minor,
+                                interpreter.micro,
+                            );
+                            if !matches {
+                                println!(
+                                    "🐍 Skipping {}, which doesn't match requires-python {}",
+                                    interpreter, requires_python
+                                );
+                            }
+                            matches
+                        })
+                        .collect(),
+                    None => found,
+                }
             } else {
-                PythonInterpreter::find_all(&target)
-                    .context("Finding python interpreters failed")?
+                Vec::new()
             };
+            for path in interpreter_sysconfig {
+                interpreter.push(PythonInterpreter::from_sysconfig_file(path, target)?);
+            }
+            if let Some(requires_python) = requires_python {
+                if let Some(interpreter) = interpreter
+                    .iter()
+                    .find(|x| !requires_python.contains(x.major, x.minor, x.micro))
+                {
+                    bail!(
+                        "{} doesn't match requires-python {} of the package",
+                        interpreter,
+                        requires_python
+                    );
+                }
+            }
 
             if interpreter.is_empty() {
                 bail!("Couldn't find any python interpreters. Please specify at least one with -i");
@@ -261,18 +758,26 @@ pub fn find_interpreter(
             interpreter
         }
         BridgeModel::Cffi => {
-            let executable = if interpreter.is_empty() {
-                target.get_python()
-            } else if interpreter.len() == 1 {
-                interpreter[0].clone()
-            } else {
+            if !interpreter_sysconfig.is_empty() {
+                bail!(
+                    "cffi needs to run python to generate the bindings, \
+                     so --interpreter-sysconfig can't be used"
+                );
+            }
+            if interpreter.len() > 1 {
                 bail!("You can only specify one python interpreter for cffi compilation");
-            };
+            }
             let err_message = "Failed to find python interpreter for generating cffi bindings";
 
-            let interpreter = PythonInterpreter::check_executable(executable, &target)
-                .context(err_msg(err_message))?
-                .ok_or_else(|| err_msg(err_message))?;
+            let interpreter = if interpreter.is_empty() {
+                PythonInterpreter::check_executable(target.get_python(), &target)
+                    .context(err_msg(err_message))?
+                    .ok_or_else(|| err_msg(err_message))?
+            } else {
+                PythonInterpreter::find_selected(interpreter, target)
+                    .context(err_msg(err_message))?
+                    .remove(0)
+            };
 
             println!("🐍 Using {} to generate the cffi bindings", interpreter);
 
@@ -305,6 +810,10 @@ mod test {
 
     use super::*;
 
+    fn root_id(cargo_metadata: &Metadata) -> PackageId {
+        cargo_metadata.resolve.clone().unwrap().root.unwrap()
+    }
+
     #[test]
     fn test_find_bridge_pyo3() {
         let pyo3_pure = MetadataCommand::new()
@@ -312,17 +821,57 @@ mod test {
             .exec()
             .unwrap();
 
-        assert!(match find_bridge(&pyo3_pure, None).unwrap() {
-            BridgeModel::Bindings(_) => true,
-            _ => false,
-        });
+        assert!(
+            match find_bridge(&pyo3_pure, &root_id(&pyo3_pure), None).unwrap() {
+                BridgeModel::Bindings(_) => true,
+                _ => false,
+            }
+        );
 
-        assert!(match find_bridge(&pyo3_pure, Some("pyo3")).unwrap() {
-            BridgeModel::Bindings(_) => true,
-            _ => false,
-        });
+        assert!(
+            match find_bridge(&pyo3_pure, &root_id(&pyo3_pure), Some("pyo3")).unwrap() {
+                BridgeModel::Bindings(_) => true,
+                _ => false,
+            }
+        );
+
+        assert!(find_bridge(&pyo3_pure, &root_id(&pyo3_pure), Some("rust-cpython")).is_err());
+    }
+
+    #[test]
+    fn test_find_abi3_version() {
+        let features = |features: &[&str]| -> Vec<String> {
+            features.iter().map(ToString::to_string).collect()
+        };
+        assert_eq!(find_abi3_version(&features(&["extension-module"])), None);
+        assert_eq!(
+            find_abi3_version(&features(&["abi3"])),
+            Some(Abi3Version::CurrentPython)
+        );
+        assert_eq!(
+            find_abi3_version(&features(&["abi3", "abi3-py38", "abi3-py36", "abi3-py37"])),
+            Some(Abi3Version::Version(3, 6))
+        );
+        assert_eq!(
+            find_abi3_version(&features(&["abi3-py310", "abi3-py39"])),
+            Some(Abi3Version::Version(3, 9))
+        );
+    }
 
-        assert!(find_bridge(&pyo3_pure, Some("rust-cpython")).is_err());
+    #[test]
+    fn test_lowest_abi3_version() {
+        let target =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        let interpreter: Vec<PythonInterpreter> = [
+            "sysconfig/cpython-linux-3.11.txt",
+            "sysconfig/pypy-linux-3.5-7.0.txt",
+            "sysconfig/cpython-linux-3.6.txt",
+        ]
+        .iter()
+        .map(|path| PythonInterpreter::from_sysconfig_file(Path::new(path), &target).unwrap())
+        .collect();
+        assert_eq!(lowest_abi3_version(&interpreter).unwrap(), (3, 6));
+        assert!(lowest_abi3_version(&interpreter[1..2]).is_err());
     }
 
     #[test]
@@ -333,12 +882,12 @@ mod test {
             .unwrap();
 
         assert_eq!(
-            find_bridge(&cffi_pure, Some("cffi")).unwrap(),
+            find_bridge(&cffi_pure, &root_id(&cffi_pure), Some("cffi")).unwrap(),
             BridgeModel::Cffi
         );
 
-        assert!(find_bridge(&cffi_pure, Some("rust-cpython")).is_err());
-        assert!(find_bridge(&cffi_pure, Some("pyo3")).is_err());
+        assert!(find_bridge(&cffi_pure, &root_id(&cffi_pure), Some("rust-cpython")).is_err());
+        assert!(find_bridge(&cffi_pure, &root_id(&cffi_pure), Some("pyo3")).is_err());
     }
 
     #[test]
@@ -349,13 +898,146 @@ mod test {
             .unwrap();
 
         assert_eq!(
-            find_bridge(&hello_world, Some("bin")).unwrap(),
+            find_bridge(&hello_world, &root_id(&hello_world), Some("bin")).unwrap(),
             BridgeModel::Bin
         );
 
-        assert!(find_bridge(&hello_world, None).is_err());
-        assert!(find_bridge(&hello_world, Some("rust-cpython")).is_err());
-        assert!(find_bridge(&hello_world, Some("pyo3")).is_err());
+        assert!(find_bridge(&hello_world, &root_id(&hello_world), None).is_err());
+        assert!(find_bridge(&hello_world, &root_id(&hello_world), Some("rust-cpython")).is_err());
+        assert!(find_bridge(&hello_world, &root_id(&hello_world), Some("pyo3")).is_err());
+    }
+
+    #[test]
+    fn test_select_bins() {
+        let hello_world = MetadataCommand::new()
+            .manifest_path(Path::new("test-crates/hello-world").join("Cargo.toml"))
+            .exec()
+            .unwrap();
+        let id = root_id(&hello_world);
+        let bin = BridgeModel::Bin;
+        let bins =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|x| x.to_string()).collect() };
+
+        assert_eq!(
+            select_bins(&hello_world, &id, &bin, &[], None).unwrap(),
+            bins(&["goodbye", "hello-world"])
+        );
+        assert_eq!(
+            select_bins(&hello_world, &id, &bin, &[], Some(bins(&["goodbye"]))).unwrap(),
+            bins(&["goodbye"])
+        );
+        // --bin takes precedence over the Cargo.toml
+        assert_eq!(
+            select_bins(
+                &hello_world,
+                &id,
+                &bin,
+                &bins(&["hello-world"]),
+                Some(bins(&["goodbye"]))
+            )
+            .unwrap(),
+            bins(&["hello-world"])
+        );
+        assert!(select_bins(&hello_world, &id, &bin, &bins(&["hello"]), None).is_err());
+        assert!(select_bins(&hello_world, &id, &bin, &[], Some(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn test_workspace() {
+        let workspace_dir = Path::new("test-crates/pyo3-workspace");
+        let workspace = MetadataCommand::new()
+            .manifest_path(workspace_dir.join("Cargo.toml"))
+            .exec()
+            .unwrap();
+        let root_manifest = workspace_dir.join("Cargo.toml").canonicalize().unwrap();
+        let member_manifest = workspace_dir
+            .join("pyo3-member")
+            .join("Cargo.toml")
+            .canonicalize()
+            .unwrap();
+
+        // The workspace root has no package, so one must be selected
+        assert!(select_package(&workspace, &root_manifest, None).is_err());
+        assert!(select_package(&workspace, &root_manifest, Some("pyo3-pure")).is_err());
+        let rust_core = select_package(&workspace, &root_manifest, Some("rust-core")).unwrap();
+        assert_eq!(rust_core.name, "rust-core");
+        let pyo3_member = select_package(&workspace, &member_manifest, None).unwrap();
+        assert_eq!(pyo3_member.name, "pyo3-member");
+
+        // Only the dependencies of the selected member count
+        assert_eq!(
+            find_bridge(&workspace, &pyo3_member.id, None).unwrap(),
+            BridgeModel::Bindings("pyo3".to_string())
+        );
+        assert!(find_bridge(&workspace, &rust_core.id, None).is_err());
+
+        // Bindings only get the binaries that were asked for, which can come from other members
+        let bindings = BridgeModel::Bindings("pyo3".to_string());
+        let cargo_toml = CargoToml::from_path(&member_manifest).unwrap();
+        assert_eq!(
+            select_bins(
+                &workspace,
+                &pyo3_member.id,
+                &bindings,
+                &[],
+                cargo_toml.bins()
+            )
+            .unwrap(),
+            vec!["rust-core".to_string()]
+        );
+        assert!(
+            select_bins(&workspace, &pyo3_member.id, &bindings, &[], None)
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            find_bin_package(&workspace, &pyo3_member.id, "rust-core")
+                .unwrap()
+                .id,
+            rust_core.id
+        );
+        assert!(select_bins(&workspace, &pyo3_member.id, &BridgeModel::Bin, &[], None).is_err());
+    }
+
+    #[test]
+    fn test_enabled_features() {
+        let package_features: HashMap<String, Vec<String>> = vec![
+            ("default", vec!["fast"]),
+            ("fast", vec![]),
+            ("numpy", vec!["pyo3/numpy", "arrays"]),
+            ("arrays", vec![]),
+            ("async", vec![]),
+        ]
+        .into_iter()
+        .map(|(name, implied)| {
+            let implied = implied.into_iter().map(ToString::to_string).collect();
+            (name.to_string(), implied)
+        })
+        .collect();
+        let enabled = |features: &[&str], cargo_extra_args: &[&str]| -> Vec<String> {
+            let features: Vec<String> = features.iter().map(ToString::to_string).collect();
+            let args: Vec<String> = cargo_extra_args.iter().map(ToString::to_string).collect();
+            let mut enabled: Vec<String> = enabled_features(&package_features, &features, &args)
+                .into_iter()
+                .collect();
+            enabled.sort();
+            enabled
+        };
+
+        assert_eq!(enabled(&[], &[]), vec!["default", "fast"]);
+        assert_eq!(
+            enabled(&["numpy"], &["--no-default-features"]),
+            vec!["arrays", "numpy"]
+        );
+        assert_eq!(
+            enabled(&[], &["--features", "numpy async", "--no-default-features"]),
+            vec!["arrays", "async", "numpy"]
+        );
+        assert_eq!(
+            enabled(&[], &["--features=async", "--release"]),
+            vec!["async", "default", "fast"]
+        );
+        assert_eq!(enabled(&[], &["--all-features"]).len(), 5);
     }
 
     #[test]
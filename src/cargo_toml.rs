@@ -1,6 +1,6 @@
 use failure::{Error, ResultExt};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::Path;
 
@@ -61,8 +61,12 @@ impl CargoToml {
         match self.package.metadata {
             Some(CargoTomlMetadata {
                 pyo3_pack:
-                    Some(RemainingCoreMetadata {
-                        scripts: Some(ref scripts),
+                    Some(Pyo3PackMetadata {
+                        core:
+                            RemainingCoreMetadata {
+                                scripts: Some(ref scripts),
+                                ..
+                            },
                         ..
                     }),
             }) => scripts.clone(),
@@ -70,13 +74,33 @@ impl CargoToml {
         }
     }
 
+    /// Returns the names of the binaries that should be put into the wheel, if the package
+    /// doesn't want all of them
+    pub fn bins(&self) -> Option<Vec<String>> {
+        self.build_settings().bins
+    }
+
+    /// Returns the cargo features that are always enabled when building wheels
+    pub fn features(&self) -> Vec<String> {
+        self.build_settings().features.unwrap_or_default()
+    }
+
+    /// Returns the mapping from cargo features to the requirements of their python extras
+    pub fn extras(&self) -> BTreeMap<String, Vec<String>> {
+        self.build_settings().extras.unwrap_or_default()
+    }
+
     /// Returns the trove classifier
     pub fn classifier(&self) -> Vec<String> {
         match self.package.metadata {
             Some(CargoTomlMetadata {
                 pyo3_pack:
-                    Some(RemainingCoreMetadata {
-                        classifier: Some(ref classifier),
+                    Some(Pyo3PackMetadata {
+                        core:
+                            RemainingCoreMetadata {
+                                classifier: Some(ref classifier),
+                                ..
+                            },
                         ..
                     }),
             }) => classifier.clone(),
@@ -84,12 +108,22 @@ impl CargoToml {
         }
     }
 
-    /// Returns the value of `[project.metadata.pyo3-pack]` or an empty stub
+    /// Returns the python metadata from `[project.metadata.pyo3-pack]` or an empty stub
     pub fn remaining_core_metadata(&self) -> RemainingCoreMetadata {
         match &self.package.metadata {
             Some(CargoTomlMetadata {
-                pyo3_pack: Some(extra_metadata),
-            }) => extra_metadata.clone(),
+                pyo3_pack: Some(pyo3_pack),
+            }) => pyo3_pack.core.clone(),
+            _ => Default::default(),
+        }
+    }
+
+    /// Returns the build settings from `[project.metadata.pyo3-pack]` or an empty stub
+    pub fn build_settings(&self) -> BuildSettings {
+        match &self.package.metadata {
+            Some(CargoTomlMetadata {
+                pyo3_pack: Some(pyo3_pack),
+            }) => pyo3_pack.build.clone(),
             _ => Default::default(),
         }
     }
@@ -98,7 +132,17 @@ impl CargoToml {
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 struct CargoTomlMetadata {
-    pyo3_pack: Option<RemainingCoreMetadata>,
+    pyo3_pack: Option<Pyo3PackMetadata>,
+}
+
+/// The `[project.metadata.pyo3-pack]` table, which mixes python metadata and build settings
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct Pyo3PackMetadata {
+    #[serde(flatten)]
+    core: RemainingCoreMetadata,
+    #[serde(flatten)]
+    build: BuildSettings,
 }
 
 /// The `[project.metadata.pyo3-pack]` with the python specific metadata
@@ -108,7 +152,6 @@ struct CargoTomlMetadata {
 /// that doesn't have an equivalent in cargo's `[package]` table
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Default)]
 #[serde(rename_all = "kebab-case")]
-#[serde(deny_unknown_fields)]
 pub struct RemainingCoreMetadata {
     pub scripts: Option<HashMap<String, String>>,
     pub classifier: Option<Vec<String>>,
@@ -121,6 +164,20 @@ pub struct RemainingCoreMetadata {
     pub provides_extra: Option<Vec<String>>,
 }
 
+/// The settings in `[project.metadata.pyo3-pack]` that control the build instead of ending up in
+/// the python metadata
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct BuildSettings {
+    /// The binaries that are put into the wheel with `-b bin`
+    pub bins: Option<Vec<String>>,
+    /// The cargo features that are enabled for all builds
+    pub features: Option<Vec<String>>,
+    /// Maps cargo features to the requirements of the python extra with the same name, which is
+    /// only added to the metadata if the feature is enabled
+    pub extras: Option<BTreeMap<String, Vec<String>>>,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -168,4 +225,47 @@ mod test {
             requires_dist
         );
     }
+
+    #[test]
+    fn test_build_settings() {
+        let cargo_toml = indoc!(
+            r#"
+            [package]
+            authors = ["konstin <konstin@mailbox.org>"]
+            name = "info-project"
+            version = "0.1.0"
+
+            [package.metadata.pyo3-pack]
+            requires-python = ">=3.6"
+            bins = ["info"]
+            features = ["simd"]
+
+            [package.metadata.pyo3-pack.extras]
+            fast = ["numpy"]
+        "#
+        );
+
+        let cargo_toml: CargoToml = toml::from_str(cargo_toml).unwrap();
+        assert_eq!(cargo_toml.bins(), Some(vec!["info".to_string()]));
+        assert_eq!(cargo_toml.features(), vec!["simd".to_string()]);
+        assert_eq!(cargo_toml.extras()["fast"], vec!["numpy".to_string()]);
+        assert_eq!(
+            cargo_toml.remaining_core_metadata().requires_python,
+            Some(">=3.6".to_string())
+        );
+
+        // Typos are still rejected
+        let typo = indoc!(
+            r#"
+            [package]
+            authors = ["konstin <konstin@mailbox.org>"]
+            name = "info-project"
+            version = "0.1.0"
+
+            [package.metadata.pyo3-pack]
+            requires-pyhton = ">=3.6"
+        "#
+        );
+        assert!(toml::from_str::<CargoToml>(typo).is_err());
+    }
 }
@@ -1,33 +1,227 @@
 use crate::build_context::BridgeModel;
+use crate::build_options::find_bin_package;
 use crate::BuildContext;
 use crate::PythonInterpreter;
 use cargo_metadata;
-use failure::{bail, Error, ResultExt};
+use failure::{bail, format_err, Error, ResultExt};
+use goblin::mach::constants::cputype::CPU_TYPE_ARM64;
+use goblin::mach::fat::FAT_MAGIC;
+use goblin::mach::load_command::CommandVariant;
+use goblin::mach::{Mach, MachO};
+use scroll::{Endian, Pread, Pwrite, BE};
 use std::collections::HashMap;
+use std::env;
+use std::fs;
 use std::fs::File;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::str;
+use std::thread;
+
+/// Runs cargo in its own target directory and collects its output instead of printing it, so
+/// that the builds for several interpreters can run at the same time
+pub(crate) struct CapturedBuild {
+    /// Used as `CARGO_TARGET_DIR`, so that the parallel builds don't wait for each other's lock on
+    /// the target directory or overwrite each other's artifacts
+    pub target_dir: PathBuf,
+    /// The output of cargo and rustc
+    pub log: String,
+}
 
 /// Builds the rust crate into a native module (i.e. an .so or .dll) for a
 /// specific python version. Returns a mapping from crate type (e.g. cdylib)
-/// to artifact location.
+/// to artifact locations.
+///
+/// For binaries, each of [BuildContext::bins] is built with its own cargo invocation, since cargo
+/// only passes the rustc extra args to a single target, and all of them are returned for `bin`.
+///
+/// For universal2, the crate is built for x86_64 and arm64 and the artifacts are merged into
+/// fat Mach-O files.
 pub fn compile(
     context: &BuildContext,
     python_interpreter: Option<&PythonInterpreter>,
     bindings_crate: &BridgeModel,
+) -> Result<HashMap<String, Vec<PathBuf>>, Error> {
+    compile_captured(context, python_interpreter, bindings_crate, None)
+}
+
+/// [compile()], which with `captured` runs cargo in the given target directory and adds its output
+/// to the log instead of printing it
+pub(crate) fn compile_captured(
+    context: &BuildContext,
+    python_interpreter: Option<&PythonInterpreter>,
+    bindings_crate: &BridgeModel,
+    mut captured: Option<&mut CapturedBuild>,
+) -> Result<HashMap<String, Vec<PathBuf>>, Error> {
+    let mut artifacts: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    match bindings_crate {
+        BridgeModel::Bin => {
+            for bin in &context.bins {
+                let bin_artifacts = compile_single(
+                    context,
+                    python_interpreter,
+                    bindings_crate,
+                    Some(bin),
+                    captured.as_deref_mut(),
+                )?;
+                for (crate_type, artifact) in bin_artifacts {
+                    artifacts.entry(crate_type).or_default().push(artifact);
+                }
+            }
+        }
+        BridgeModel::Cffi | BridgeModel::Bindings(_) | BridgeModel::BindingsAbi3(_, _) => {
+            let lib_artifacts =
+                compile_single(context, python_interpreter, bindings_crate, None, captured)?;
+            for (crate_type, artifact) in lib_artifacts {
+                artifacts.insert(crate_type, vec![artifact]);
+            }
+        }
+    }
+    Ok(artifacts)
+}
+
+/// Builds either the lib or the given binary, for universal2 as fat Mach-O files
+fn compile_single(
+    context: &BuildContext,
+    python_interpreter: Option<&PythonInterpreter>,
+    bindings_crate: &BridgeModel,
+    bin: Option<&str>,
+    captured: Option<&mut CapturedBuild>,
 ) -> Result<HashMap<String, PathBuf>, Error> {
+    if context.target.is_universal2() {
+        compile_universal2(context, python_interpreter, bindings_crate, bin, captured)
+    } else {
+        compile_target(
+            context,
+            python_interpreter,
+            bindings_crate,
+            bin,
+            None,
+            captured,
+        )
+    }
+}
+
+/// Builds the crate for x86_64-apple-darwin and aarch64-apple-darwin and merges each pair of
+/// artifacts into a fat Mach-O file in `target/universal2-apple-darwin/<profile>`
+fn compile_universal2(
+    context: &BuildContext,
+    python_interpreter: Option<&PythonInterpreter>,
+    bindings_crate: &BridgeModel,
+    bin: Option<&str>,
+    mut captured: Option<&mut CapturedBuild>,
+) -> Result<HashMap<String, PathBuf>, Error> {
+    let x86_64 = compile_target(
+        context,
+        python_interpreter,
+        bindings_crate,
+        bin,
+        Some("x86_64-apple-darwin"),
+        captured.as_deref_mut(),
+    )?;
+    let aarch64 = compile_target(
+        context,
+        python_interpreter,
+        bindings_crate,
+        bin,
+        Some("aarch64-apple-darwin"),
+        captured,
+    )?;
+
+    let mut artifacts = HashMap::new();
+    for (crate_type, x86_64_artifact) in x86_64 {
+        let aarch64_artifact = aarch64.get(&crate_type).ok_or_else(|| {
+            format_err!(
+                "Cargo didn't build the {} for aarch64-apple-darwin",
+                crate_type
+            )
+        })?;
+        // The artifacts are in target/<target triple>/<profile>/
+        let profile_dir = x86_64_artifact.parent().unwrap();
+        let target_dir = profile_dir.parent().unwrap().parent().unwrap();
+        let out_dir = target_dir
+            .join("universal2-apple-darwin")
+            .join(profile_dir.file_name().unwrap());
+        fs::create_dir_all(&out_dir)?;
+        let universal2_artifact = out_dir.join(x86_64_artifact.file_name().unwrap());
+
+        let slices = [fs::read(&x86_64_artifact)?, fs::read(aarch64_artifact)?];
+        let fat = create_fat_macho(&slices).context(format!(
+            "Failed to merge {} and {} into a universal2 binary",
+            x86_64_artifact.display(),
+            aarch64_artifact.display()
+        ))?;
+        fs::write(&universal2_artifact, fat)?;
+        artifacts.insert(crate_type, universal2_artifact);
+    }
+
+    Ok(artifacts)
+}
+
+/// Returns the cargo extra args without `--features`, `--all-features` and
+/// `--no-default-features`, which cargo rejects for a package other than the current one
+fn without_feature_args(cargo_extra_args: &[String]) -> Vec<&str> {
+    let mut args = Vec::new();
+    let mut iter = cargo_extra_args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--features" => {
+                iter.next();
+            }
+            "--all-features" | "--no-default-features" => {}
+            arg if arg.starts_with("--features=") => {}
+            arg => args.push(arg),
+        }
+    }
+    args
+}
+
+/// Builds the lib or the given binary of the crate for the given target triple, or without
+/// `--target` (unless given in the cargo extra args) if none is given
+fn compile_target(
+    context: &BuildContext,
+    python_interpreter: Option<&PythonInterpreter>,
+    bindings_crate: &BridgeModel,
+    bin: Option<&str>,
+    target_triple: Option<&str>,
+    mut captured: Option<&mut CapturedBuild>,
+) -> Result<HashMap<String, PathBuf>, Error> {
+    let features = context.features.join(",");
     let mut shared_args = vec!["--manifest-path", context.manifest_path.to_str().unwrap()];
 
-    // We need to pass --bins / --lib to set the rustc extra args later
-    // TODO: What do we do when there are multiple bin targets?
-    match bindings_crate {
-        BridgeModel::Bin => shared_args.push("--bins"),
-        BridgeModel::Cffi | BridgeModel::Bindings(_) => shared_args.push("--lib"),
+    if let Some(target_triple) = target_triple {
+        shared_args.extend(&["--target", target_triple]);
     }
 
-    shared_args.extend(context.cargo_extra_args.iter().map(String::as_str));
+    // We need to pass --bin / --lib to set the rustc extra args later, which cargo only accepts
+    // for a single target. Binaries can also be in another member of the workspace
+    let package_id = match bin {
+        Some(bin) => {
+            let package = find_bin_package(&context.cargo_metadata, &context.package_id, bin)
+                .ok_or_else(|| format_err!("There is no binary called {}", bin))?;
+            if package.id != context.package_id {
+                shared_args.extend(&["--package", &package.name]);
+            }
+            shared_args.extend(&["--bin", bin]);
+            &package.id
+        }
+        None => {
+            shared_args.push("--lib");
+            &context.package_id
+        }
+    };
+
+    // The features belong to the package, so they can't be passed for other workspace members,
+    // neither from the metadata nor from the cargo extra args
+    if package_id == &context.package_id {
+        if !features.is_empty() {
+            shared_args.extend(&["--features", &features]);
+        }
+        shared_args.extend(context.cargo_extra_args.iter().map(String::as_str));
+    } else {
+        shared_args.extend(without_feature_args(&context.cargo_extra_args));
+    }
 
     if context.release {
         shared_args.push("--release");
@@ -41,11 +235,9 @@ pub fn compile(
         .map(String::as_str)
         .collect();
 
-    if context.target.is_macos() {
-        if let BridgeModel::Bindings(_) = bindings_crate {
-            let mac_args = &["-C", "link-arg=-undefined", "-C", "link-arg=dynamic_lookup"];
-            rustc_args.extend(mac_args);
-        }
+    if context.target.is_macos() && bindings_crate.is_bindings() {
+        let mac_args = &["-C", "link-arg=-undefined", "-C", "link-arg=dynamic_lookup"];
+        rustc_args.extend(mac_args);
     }
 
     if context.strip {
@@ -71,12 +263,47 @@ pub fn compile(
         // but forwarding stderr is still useful in case there some non-json error
         .stderr(Stdio::inherit());
 
+    if let Some(ref captured) = captured {
+        build_command
+            .env("CARGO_TARGET_DIR", &captured.target_dir)
+            .stderr(Stdio::piped());
+    }
+
     if let Some(python_interpreter) = python_interpreter {
-        build_command.env("PYTHON_SYS_EXECUTABLE", &python_interpreter.executable);
+        match python_interpreter.cross_compile {
+            // pyo3 doesn't run python if it gets the cross compiling variables. Paths set by the
+            // user, e.g. to point into a sysroot, take precedence over those from the sysconfig
+            Some(ref cross_compile) => {
+                build_command.env(
+                    "PYO3_CROSS_PYTHON_VERSION",
+                    format!("{}.{}", python_interpreter.major, python_interpreter.minor),
+                );
+                if env::var_os("PYO3_CROSS_LIB_DIR").is_none() {
+                    build_command.env("PYO3_CROSS_LIB_DIR", &cross_compile.lib_dir);
+                }
+                if let Some(ref include_dir) = cross_compile.include_dir {
+                    if env::var_os("PYO3_CROSS_INCLUDE_DIR").is_none() {
+                        build_command.env("PYO3_CROSS_INCLUDE_DIR", include_dir);
+                    }
+                }
+            }
+            None => {
+                build_command.env("PYTHON_SYS_EXECUTABLE", &python_interpreter.executable);
+            }
+        }
     }
 
     let mut cargo_build = build_command.spawn().context("Failed to run cargo")?;
 
+    // stderr must be read while we're reading stdout, otherwise cargo blocks once the pipe is full
+    let stderr_reader = cargo_build.stderr.take().map(|mut stderr| {
+        thread::spawn(move || {
+            let mut output = String::new();
+            let _ = stderr.read_to_string(&mut output);
+            output
+        })
+    });
+
     let mut artifacts = HashMap::new();
 
     let stream = cargo_build
@@ -85,24 +312,23 @@ pub fn compile(
         .expect("Cargo build should have a stdout");
     for message in cargo_metadata::parse_messages(stream) {
         match message.context("Failed to parse message coming from cargo")? {
-            cargo_metadata::Message::CompilerArtifact(artifact) => {
-                let crate_name = &context.cargo_metadata[&artifact.package_id].name;
-
-                // Extract the location of the .so/.dll/etc. from cargo's json output
-                if crate_name == &context.metadata21.name {
-                    let tuples = artifact
-                        .target
-                        .crate_types
-                        .into_iter()
-                        .zip(artifact.filenames);
-                    for (crate_type, filename) in tuples {
-                        artifacts.insert(crate_type, filename);
-                    }
+            // Extract the location of the .so/.dll/etc. from cargo's json output
+            cargo_metadata::Message::CompilerArtifact(artifact)
+                if &artifact.package_id == package_id =>
+            {
+                let tuples = artifact
+                    .target
+                    .crate_types
+                    .into_iter()
+                    .zip(artifact.filenames);
+                for (crate_type, filename) in tuples {
+                    artifacts.insert(crate_type, filename);
                 }
             }
-            cargo_metadata::Message::CompilerMessage(msg) => {
-                println!("{}", msg.message);
-            }
+            cargo_metadata::Message::CompilerMessage(msg) => match captured {
+                Some(ref mut captured) => captured.log.push_str(&format!("{}\n", msg.message)),
+                None => println!("{}", msg.message),
+            },
             _ => (),
         }
     }
@@ -111,6 +337,13 @@ pub fn compile(
         .wait()
         .expect("Failed to wait on cargo child process");
 
+    if let (Some(stderr_reader), Some(captured)) = (stderr_reader, captured) {
+        let stderr = stderr_reader
+            .join()
+            .expect("Reading the output of cargo panicked");
+        captured.log.push_str(&stderr);
+    }
+
     if !status.success() {
         bail!(
             r#"Cargo build finished with "{}": `{}`"#,
@@ -161,3 +394,164 @@ pub fn warn_missing_py_init(artifact: &PathBuf, module_name: &str) -> Result<(),
 
     Ok(())
 }
+
+/// goblin doesn't know this load command yet
+const LC_BUILD_VERSION: u32 = 0x32;
+
+/// Returns the minimum mac os version of a single architecture Mach-O as encoded in the load
+/// commands, i.e. X.Y.Z as xxxx.yy.zz
+fn get_encoded_macos_version(macho: &MachO, buffer: &[u8]) -> Result<Option<u32>, Error> {
+    let endian = if macho.little_endian {
+        Endian::Little
+    } else {
+        Endian::Big
+    };
+    for load_command in &macho.load_commands {
+        match load_command.command {
+            CommandVariant::VersionMinMacosx(ref command) => return Ok(Some(command.version)),
+            // The layout is cmd, cmdsize, platform, minos, sdk and ntools, all as u32
+            CommandVariant::Unimplemented(ref command) if command.cmd == LC_BUILD_VERSION => {
+                return Ok(Some(
+                    buffer.pread_with::<u32>(load_command.offset + 12, endian)?,
+                ));
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the minimum mac os version of a Mach-O file, e.g. `10.9`, which is read from the
+/// LC_BUILD_VERSION or LC_VERSION_MIN_MACOSX load command. For fat binaries, this is the highest
+/// version of all architectures. Returns `None` for all other files
+pub fn get_macos_version_from_artifact(artifact: &Path) -> Result<Option<String>, Error> {
+    let mut fd = File::open(artifact)?;
+    let mut buffer = Vec::new();
+    fd.read_to_end(&mut buffer)?;
+    let version = match goblin::Object::parse(&buffer)? {
+        goblin::Object::Mach(Mach::Binary(macho)) => get_encoded_macos_version(&macho, &buffer)?,
+        goblin::Object::Mach(Mach::Fat(fat)) => {
+            let mut versions = Vec::new();
+            for (index, arch) in fat.arches()?.iter().enumerate() {
+                let slice = arch.slice(&buffer);
+                versions.extend(get_encoded_macos_version(&fat.get(index)?, slice)?);
+            }
+            versions.into_iter().max()
+        }
+        _ => return Ok(None),
+    };
+    Ok(version.map(|version| format!("{}.{}", version >> 16, (version >> 8) & 0xff)))
+}
+
+/// Merges single architecture Mach-O files into a fat Mach-O file, like `lipo -create`
+pub fn create_fat_macho(slices: &[Vec<u8>]) -> Result<Vec<u8>, Error> {
+    // The fat header and the fat_arch entries are big endian, with five u32 per entry
+    let header_size = 8 + 20 * slices.len();
+    let mut headers = vec![0; header_size];
+    headers.pwrite_with(FAT_MAGIC, 0, BE)?;
+    headers.pwrite_with(slices.len() as u32, 4, BE)?;
+
+    let mut fat = Vec::new();
+    let mut offset = header_size;
+    for (index, slice) in slices.iter().enumerate() {
+        let header = match goblin::Object::parse(slice)? {
+            goblin::Object::Mach(Mach::Binary(macho)) => macho.header,
+            _ => bail!("Only single architecture Mach-O files can be merged"),
+        };
+        // Like lipo, arm64 slices are aligned to 16KiB pages and all others to 4KiB pages
+        let align: u32 = if header.cputype == CPU_TYPE_ARM64 {
+            14
+        } else {
+            12
+        };
+        let alignment = 1 << align;
+        offset = (offset + alignment - 1) & !(alignment - 1);
+
+        let entry = 8 + 20 * index;
+        headers.pwrite_with(header.cputype, entry, BE)?;
+        headers.pwrite_with(header.cpusubtype, entry + 4, BE)?;
+        headers.pwrite_with(offset as u32, entry + 8, BE)?;
+        headers.pwrite_with(slice.len() as u32, entry + 12, BE)?;
+        headers.pwrite_with(align, entry + 16, BE)?;
+
+        fat.resize(offset - header_size, 0);
+        fat.extend_from_slice(slice);
+        offset += slice.len();
+    }
+    headers.extend(fat);
+    Ok(headers)
+}
+
+/// Returns the mac os version the artifact was built for, which is taken from the artifact itself
+/// or from `MACOSX_DEPLOYMENT_TARGET`, e.g. `10.9`
+pub fn get_macos_version(artifact: &Path) -> Result<Option<String>, Error> {
+    if let Some(version) = get_macos_version_from_artifact(artifact)? {
+        return Ok(Some(version));
+    }
+    Ok(env::var("MACOSX_DEPLOYMENT_TARGET").ok())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_without_feature_args() {
+        let args: Vec<String> = [
+            "--features",
+            "numpy fast",
+            "--locked",
+            "--features=simd",
+            "--all-features",
+            "--no-default-features",
+            "-v",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+        assert_eq!(without_feature_args(&args), vec!["--locked", "-v"]);
+    }
+
+    #[test]
+    fn test_macos_version() {
+        let versions = [
+            ("test-data/libversionmin.dylib", Some("10.9".to_string())),
+            ("test-data/libbuildversion.dylib", Some("11.0".to_string())),
+            ("test-data/libcompliant.so.1", None),
+        ];
+        for (artifact, version) in &versions {
+            assert_eq!(
+                &get_macos_version_from_artifact(Path::new(artifact)).unwrap(),
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn test_create_fat_macho() {
+        let x86_64 = fs::read("test-data/libversionmin.dylib").unwrap();
+        let mut arm64 = fs::read("test-data/libbuildversion.dylib").unwrap();
+        arm64.pwrite_with(CPU_TYPE_ARM64, 4, scroll::LE).unwrap();
+
+        let fat = create_fat_macho(&[x86_64.clone(), arm64.clone()]).unwrap();
+        let arches = match goblin::Object::parse(&fat).unwrap() {
+            goblin::Object::Mach(Mach::Fat(fat)) => fat.arches().unwrap(),
+            _ => panic!("Expected a fat Mach-O"),
+        };
+        assert_eq!(arches.len(), 2);
+        assert_eq!(arches[0].offset, 4096);
+        assert_eq!(arches[1].offset, 16384);
+        assert_eq!(arches[1].cputype, CPU_TYPE_ARM64);
+        assert_eq!(arches[0].slice(&fat), &x86_64[..]);
+        assert_eq!(arches[1].slice(&fat), &arm64[..]);
+
+        // The newer version of the two architectures is the one of the fat binary
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("libuniversal2.dylib");
+        fs::write(&path, fat).unwrap();
+        assert_eq!(
+            get_macos_version_from_artifact(&path).unwrap(),
+            Some("11.0".to_string())
+        );
+    }
+}
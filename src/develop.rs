@@ -1,7 +1,7 @@
 use std::fs;
 use std::path::Path;
 
-use failure::{format_err, Context, Error, ResultExt};
+use failure::{Context, Error, ResultExt};
 
 use crate::build_context::BridgeModel;
 use crate::compile::compile;
@@ -14,9 +14,11 @@ use crate::Target;
 /// Installs a crate by compiling it and copying the shared library to the right directory
 ///
 /// Works only in virtualenvs.
+#[allow(clippy::too_many_arguments)]
 pub fn develop(
     bindings: Option<String>,
     manifest_file: &Path,
+    package: Option<String>,
     cargo_extra_args: Vec<String>,
     rustc_extra_args: Vec<String>,
     venv_dir: &Path,
@@ -33,14 +35,20 @@ pub fn develop(
 
     let build_options = BuildOptions {
         manylinux: Manylinux::Off,
+        manylinux_policy: None,
         interpreter: vec![target.get_python()],
+        interpreter_sysconfig: vec![],
         bindings,
+        bins: Vec::new(),
         manifest_path: manifest_file.to_path_buf(),
+        package,
         out: None,
         skip_auditwheel: false,
         target: None,
+        universal2: false,
         cargo_extra_args,
         rustc_extra_args,
+        jobs: 1,
     };
 
     let build_context = build_options.into_build_context(release, strip)?;
@@ -50,22 +58,8 @@ pub fn develop(
     let context = "Failed to build a native library through cargo";
 
     match build_context.bridge {
-        BridgeModel::Bin => {
-            let artifacts = compile(&build_context, None, &BridgeModel::Bin).context(context)?;
-
-            let artifact = artifacts
-                .get("bin")
-                .ok_or_else(|| format_err!("Cargo didn't build a binary"))?;
-
-            // Copy the artifact into the same folder as pip and python
-            let bin_name = artifact.file_name().unwrap();
-            let bin_path = target.get_venv_bin_dir(&venv_dir).join(bin_name);
-            fs::copy(&artifact, &bin_path).context(format!(
-                "Failed to copy {} to {}",
-                artifact.display(),
-                bin_path.display()
-            ))?;
-        }
+        // The binaries are installed below, like the ones that come with the other bridges
+        BridgeModel::Bin => {}
         BridgeModel::Cffi => {
             let artifact = build_context.compile_cdylib(None, None).context(context)?;
 
@@ -81,21 +75,44 @@ pub fn develop(
                 true,
             )?;
         }
-        BridgeModel::Bindings(_) => {
+        BridgeModel::Bindings(_) | BridgeModel::BindingsAbi3(_, _) => {
             let artifact = build_context
                 .compile_cdylib(Some(&interpreter), Some(&build_context.module_name))
                 .context(context)?;
 
+            // abi3 libraries have a version independent name
+            let python_interpreter = match build_context.bridge {
+                BridgeModel::BindingsAbi3(_, _) => None,
+                _ => Some(&interpreter),
+            };
+
             write_bindings_module(
                 &mut builder,
                 &build_context.project_layout,
                 &build_context.module_name,
                 &artifact,
-                &interpreter,
+                python_interpreter,
+                &interpreter.target,
                 true,
             )?;
         }
     }
 
+    let artifacts = compile(&build_context, Some(&interpreter), &BridgeModel::Bin)
+        .context(context)?
+        .remove("bin")
+        .unwrap_or_default();
+
+    // Copy the binaries into the same folder as pip and python
+    for artifact in artifacts {
+        let bin_name = artifact.file_name().unwrap();
+        let bin_path = target.get_venv_bin_dir(&venv_dir).join(bin_name);
+        fs::copy(&artifact, &bin_path).context(format!(
+            "Failed to copy {} to {}",
+            artifact.display(),
+            bin_path.display()
+        ))?;
+    }
+
     Ok(())
 }
@@ -6,6 +6,7 @@ import sysconfig
 metadata = {
     "major": sys.version_info.major,
     "minor": sys.version_info.minor,
+    "micro": sys.version_info.micro,
     "abiflags": sysconfig.get_config_var("ABIFLAGS"),
     "interpreter": platform.python_implementation().lower(),
     "ext_suffix": sysconfig.get_config_var("EXT_SUFFIX"),
@@ -15,6 +16,8 @@ metadata = {
     "d": sysconfig.get_config_var("Py_DEBUG") == 1,
     # This one isn't technically necessary, but still very useful for sanity checks
     "platform": sys.platform,
+    # Contains the release on the bsds, e.g. "freebsd-12.0-RELEASE-amd64"
+    "sysconfig_platform": sysconfig.get_platform(),
 }
 
 print(json.dumps(metadata))
@@ -0,0 +1,279 @@
+use crate::python_interpreter::IntepreterMetadataMessage;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tempfile::NamedTempFile;
+
+/// Identifies the executable behind a cache entry. If the size or the modification time of the
+/// executable change, e.g. because the interpreter was upgraded, the entry is stale
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+struct FileStamp {
+    /// Seconds since the unix epoch
+    modified: u64,
+    /// The sub-second part of the modification time
+    modified_nanos: u32,
+    size: u64,
+}
+
+impl FileStamp {
+    /// Returns `None` if the file doesn't exist or the platform has no modification times
+    fn of(path: &Path) -> Option<FileStamp> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(FileStamp {
+            modified: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+            size: metadata.len(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    stamp: FileStamp,
+    metadata: IntepreterMetadataMessage,
+}
+
+/// The format of the cache file
+#[derive(Serialize, Deserialize, Default)]
+struct CacheFile {
+    /// A hash of get_interpreter_metadata.py, so that a pyo3-pack that asks for different
+    /// metadata doesn't use the entries of another version
+    script: String,
+    /// The entries by the canonical path of the executable
+    interpreters: BTreeMap<PathBuf, CacheEntry>,
+}
+
+/// Caches the output of get_interpreter_metadata.py on disk, since running every interpreter on
+/// every build is slow, especially for conda on windows.
+///
+/// The entries are keyed by the canonical path of the executable and invalidated when its size or
+/// modification time changes. Executables that aren't native binaries, such as pyenv's shims, are
+/// never cached. Failing to read or write the cache is never an error, the
+/// interpreters are then just run again.
+pub(crate) struct InterpreterCache {
+    /// `None` if there's no cache directory, e.g. because `HOME` isn't set
+    path: Option<PathBuf>,
+    script: String,
+    interpreters: BTreeMap<PathBuf, CacheEntry>,
+    /// Ignores the existing entries, so that every interpreter is run again
+    refresh: bool,
+    changed: bool,
+}
+
+/// Returns the platform's directory for caches, i.e. `$XDG_CACHE_HOME` or `~/.cache` on linux and
+/// the bsds, `~/Library/Caches` on mac os and `%LOCALAPPDATA%` on windows
+fn cache_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| Path::new(&home).join("Library").join("Caches"))
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| Path::new(dir).is_absolute())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+    }
+}
+
+/// Returns the canonical path of an executable, which is either a path or a name that is looked
+/// up in PATH, e.g. `python3.7`
+fn canonicalize_executable(executable: &Path) -> Option<PathBuf> {
+    if executable.components().count() > 1 {
+        return fs::canonicalize(executable).ok();
+    }
+    let mut names = vec![executable.to_path_buf()];
+    if cfg!(windows) && executable.extension().is_none() {
+        names.push(executable.with_extension("exe"));
+    }
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|candidate| candidate.is_file())
+        .and_then(|candidate| fs::canonicalize(candidate).ok())
+}
+
+/// Returns true if the file is a native executable, i.e. an elf, mach-o or pe file. Shims of
+/// version managers such as pyenv are scripts that run a different interpreter depending on the
+/// environment, e.g. `PYENV_VERSION`, so their stamp doesn't identify the interpreter.
+fn is_native_executable(path: &Path) -> bool {
+    let mut magic = [0; 4];
+    let read = fs::File::open(path).and_then(|mut file| file.read_exact(&mut magic));
+    if read.is_err() {
+        return false;
+    }
+    magic == *b"\x7fELF"
+        || magic.starts_with(b"MZ")
+        || [0xfeed_face, 0xfeed_facf, 0xcafe_babe].contains(&u32::from_be_bytes(magic))
+        || [0xfeed_face, 0xfeed_facf].contains(&u32::from_le_bytes(magic))
+}
+
+/// Returns the path under which the metadata of the executable is cached, which is its canonical
+/// path, or `None` if it can't be cached because it's not a native executable
+fn cache_key(executable: &Path) -> Option<PathBuf> {
+    canonicalize_executable(executable).filter(|canonical| is_native_executable(canonical))
+}
+
+impl InterpreterCache {
+    /// Loads the cache from `pyo3-pack/interpreters.json` in the platform's cache directory.
+    /// With `refresh`, the existing entries are ignored and overwritten on [InterpreterCache::save]
+    pub fn load(refresh: bool) -> InterpreterCache {
+        let path = cache_dir().map(|dir| dir.join("pyo3-pack").join("interpreters.json"));
+        InterpreterCache::load_from(path, refresh)
+    }
+
+    /// Loads the cache from the given file and drops the stale entries
+    fn load_from(path: Option<PathBuf>, refresh: bool) -> InterpreterCache {
+        let script = Sha256::digest(include_str!("get_interpreter_metadata.py").as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let cache_file: CacheFile = path
+            .as_ref()
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|contents| serde_json::from_slice(&contents).ok())
+            .filter(|cache_file: &CacheFile| cache_file.script == script)
+            .unwrap_or_default();
+
+        let count = cache_file.interpreters.len();
+        let interpreters: BTreeMap<PathBuf, CacheEntry> = cache_file
+            .interpreters
+            .into_iter()
+            .filter(|(executable, entry)| FileStamp::of(executable) == Some(entry.stamp))
+            .collect();
+        let changed = interpreters.len() != count;
+
+        InterpreterCache {
+            path,
+            script,
+            interpreters,
+            refresh,
+            changed,
+        }
+    }
+
+    /// Returns the cached metadata if the executable didn't change since it was cached
+    pub fn get(&self, executable: &Path) -> Option<IntepreterMetadataMessage> {
+        if self.refresh {
+            return None;
+        }
+        let canonical = cache_key(executable)?;
+        let entry = self.interpreters.get(&canonical)?;
+        if FileStamp::of(&canonical) != Some(entry.stamp) {
+            return None;
+        }
+        Some(entry.metadata.clone())
+    }
+
+    /// Adds or replaces the metadata of an executable
+    pub fn insert(&mut self, executable: &Path, metadata: IntepreterMetadataMessage) {
+        let canonical = match cache_key(executable) {
+            Some(canonical) => canonical,
+            None => return,
+        };
+        let stamp = match FileStamp::of(&canonical) {
+            Some(stamp) => stamp,
+            None => return,
+        };
+        self.interpreters
+            .insert(canonical, CacheEntry { stamp, metadata });
+        self.changed = true;
+    }
+
+    /// Writes the cache if it changed. The file is replaced atomically, so that concurrent runs
+    /// of pyo3-pack don't see a half written cache. Failures are only printed as warnings
+    pub fn save(self) {
+        let path = match self.path {
+            Some(ref path) if self.changed => path.clone(),
+            _ => return,
+        };
+        let cache_file = CacheFile {
+            script: self.script,
+            interpreters: self.interpreters,
+        };
+        let write = || -> Result<(), failure::Error> {
+            let dir = path.parent().unwrap_or_else(|| Path::new("."));
+            fs::create_dir_all(dir)?;
+            let mut file = NamedTempFile::new_in(dir)?;
+            file.write_all(&serde_json::to_vec(&cache_file)?)?;
+            file.persist(&path)?;
+            Ok(())
+        };
+        if let Err(err) = write() {
+            eprintln!(
+                "⚠ Warning: Failed to write the interpreter cache to {}: {}",
+                path.display(),
+                err
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn message(minor: usize) -> IntepreterMetadataMessage {
+        let json = format!(
+            r#"{{"major": 3, "minor": {}, "abiflags": "m", "interpreter": "cpython",
+            "ext_suffix": ".cpython-37m-x86_64-linux-gnu.so", "abi_tag": "37m", "m": true,
+            "u": false, "d": false, "platform": "linux", "sysconfig_platform": "linux-x86_64"}}"#,
+            minor
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_interpreter_cache() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache_path = Some(tempdir.path().join("cache").join("interpreters.json"));
+        let python = tempdir.path().join("python3.7");
+        fs::write(&python, b"\x7fELF\x02").unwrap();
+        let pypy = tempdir.path().join("pypy3");
+        fs::write(&pypy, b"\x7fELF\x02").unwrap();
+        // Like pyenv's shims, which run whatever interpreter is selected
+        let shim = tempdir.path().join("python3");
+        fs::write(&shim, "#!/usr/bin/env bash\nexec pyenv exec python3").unwrap();
+
+        let mut cache = InterpreterCache::load_from(cache_path.clone(), false);
+        assert!(cache.get(&python).is_none());
+        cache.insert(&python, message(7));
+        cache.insert(&pypy, message(6));
+        cache.insert(&shim, message(8));
+        assert!(cache.get(&shim).is_none());
+        cache.save();
+
+        let cache = InterpreterCache::load_from(cache_path.clone(), false);
+        let cached = serde_json::to_value(cache.get(&python).unwrap()).unwrap();
+        assert_eq!(cached["minor"], 7);
+        assert!(InterpreterCache::load_from(cache_path.clone(), true)
+            .get(&python)
+            .is_none());
+
+        // A changed executable invalidates the entry, a deleted one is dropped
+        fs::write(&python, b"\x7fELF\x02\x01").unwrap();
+        fs::remove_file(&pypy).unwrap();
+        let cache = InterpreterCache::load_from(cache_path.clone(), false);
+        assert!(cache.get(&python).is_none());
+        assert!(cache.interpreters.is_empty());
+        assert!(cache.changed);
+    }
+
+    #[test]
+    fn test_invalid_cache() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache_path = tempdir.path().join("interpreters.json");
+        fs::write(&cache_path, "{").unwrap();
+        let cache = InterpreterCache::load_from(Some(cache_path.clone()), false);
+        assert!(cache.interpreters.is_empty());
+
+        fs::write(&cache_path, r#"{"script": "other", "interpreters": {}}"#).unwrap();
+        let cache = InterpreterCache::load_from(Some(cache_path), false);
+        assert!(cache.interpreters.is_empty());
+    }
+}
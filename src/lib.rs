@@ -28,7 +28,10 @@
 #![deny(missing_docs)]
 
 #[cfg(feature = "auditwheel")]
-pub use crate::auditwheel::{auditwheel_rs, AuditWheelError};
+pub use crate::auditwheel::{
+    audit_pypy, audit_wheel, auditwheel_rs, find_best_policy, AuditWheelError, ElfAudit,
+    PolicyAudit, WheelAudit,
+};
 pub use crate::build_context::BridgeModel;
 pub use crate::build_context::BuildContext;
 pub use crate::build_options::BuildOptions;
@@ -39,7 +42,8 @@ pub use crate::metadata::{Metadata21, WheelMetadata};
 pub use crate::module_writer::{
     write_dist_info, ModuleWriter, PathWriter, SDistWriter, WheelWriter,
 };
-pub use crate::python_interpreter::PythonInterpreter;
+pub use crate::policy::{CheckLevel, Policy};
+pub use crate::python_interpreter::{CrossCompileInfo, InterpreterSource, PythonInterpreter};
 pub use crate::target::{Manylinux, Target};
 pub use source_distribution::{get_pyproject_toml, source_distribution};
 #[cfg(feature = "upload")]
@@ -55,11 +59,16 @@ mod build_options;
 mod cargo_toml;
 mod compile;
 mod develop;
+mod interpreter_cache;
 mod metadata;
 mod module_writer;
+mod pep440;
+mod policy;
 mod python_interpreter;
 #[cfg(feature = "upload")]
 mod registry;
+#[cfg(feature = "auditwheel")]
+mod repair;
 mod source_distribution;
 mod target;
 #[cfg(feature = "upload")]
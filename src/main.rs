@@ -18,6 +18,11 @@ use pyo3_pack::{
 use std::path::PathBuf;
 use std::{env, fs};
 use structopt::StructOpt;
+#[cfg(feature = "auditwheel")]
+use {
+    pyo3_pack::{audit_wheel, Policy, WheelAudit},
+    std::path::Path,
+};
 #[cfg(feature = "upload")]
 use {
     pyo3_pack::{upload, Registry, UploadError},
@@ -143,7 +148,11 @@ enum Opt {
     },
     #[structopt(name = "list-python")]
     /// Searches and lists the available python installations
-    ListPython,
+    ListPython {
+        /// Runs all interpreters again instead of using the metadata cached from previous runs
+        #[structopt(long = "refresh")]
+        refresh: bool,
+    },
     #[structopt(name = "develop")]
     /// Installs the crate as module in the current virtualenv
     ///
@@ -160,6 +169,10 @@ enum Opt {
         )]
         /// The path to the Cargo.toml
         manifest_path: PathBuf,
+        /// The package to build out of the members of the workspace. Defaults to the package of
+        /// the Cargo.toml given with --manifest-path
+        #[structopt(long = "package")]
+        package: Option<String>,
         /// Pass --release to cargo
         #[structopt(long)]
         release: bool,
@@ -197,6 +210,21 @@ enum Opt {
         #[structopt(short, long, parse(from_os_str))]
         out: Option<PathBuf>,
     },
+    #[cfg(feature = "auditwheel")]
+    #[structopt(name = "audit")]
+    /// Checks the native libraries in a wheel for manylinux compliance, like `auditwheel show`
+    Audit {
+        /// The wheel to check
+        #[structopt(parse(from_os_str))]
+        wheel: PathBuf,
+        /// Print the report as json
+        #[structopt(long)]
+        json: bool,
+        /// A policy file in the format of auditwheel's policy.json, which replaces the embedded
+        /// manylinux policies
+        #[structopt(long = "manylinux-policy", parse(from_os_str))]
+        manylinux_policy: Option<PathBuf>,
+    },
     /// Backend for the PEP 517 integration. Not for human consumption
     ///
     /// The commands are meant to be called from the python PEP 517
@@ -266,6 +294,9 @@ fn pep517(subcommand: PEP517Command) -> Result<(), Error> {
                 BridgeModel::Bindings(_) => {
                     vec![context.interpreter[0].get_tag(&context.manylinux)]
                 }
+                BridgeModel::BindingsAbi3(major, minor) => {
+                    vec![context.target.get_abi3_tag(major, minor, &context.manylinux)]
+                }
                 BridgeModel::Bin | BridgeModel::Cffi => {
                     context.target.get_universal_tags(&context.manylinux).1
                 }
@@ -298,6 +329,45 @@ fn pep517(subcommand: PEP517Command) -> Result<(), Error> {
     Ok(())
 }
 
+/// Prints the result of `pyo3-pack audit` in a similar format to `auditwheel show`
+#[cfg(feature = "auditwheel")]
+fn print_audit(wheel: &Path, audit: &WheelAudit) {
+    if audit.files.is_empty() {
+        println!(
+            "🔍 {} doesn't contain any native libraries",
+            wheel.display()
+        );
+        return;
+    }
+    for file in &audit.files {
+        println!("🔍 {}", file.path);
+        println!("   Needed libraries: {}", file.needed.join(", "));
+        let symbol_versions: Vec<String> = file
+            .symbol_versions
+            .iter()
+            .map(|(name, versions)| format!("{} {}", name, versions.join(", ")))
+            .collect();
+        println!("   Symbol versions: {}", symbol_versions.join("; "));
+        for (policy, reason) in &file.rejected {
+            println!("   Not {}: {}", policy, reason);
+        }
+        if let Some(ref pypy_error) = file.pypy_error {
+            println!("   Not usable with PyPy: {}", pypy_error);
+        }
+        match file.policy {
+            Some(ref policy) => println!("   Complies with {}", policy),
+            None => println!("   Complies with no manylinux policy"),
+        }
+    }
+    match audit.policy {
+        Some(ref policy) => println!("✨ {} is consistent with {}", wheel.display(), policy),
+        None => println!(
+            "💥 {} is not consistent with any manylinux policy",
+            wheel.display()
+        ),
+    }
+}
+
 /// Handles authentification/keyring integration and retrying of the publish subcommand
 #[cfg(feature = "upload")]
 fn upload_ui(build: BuildOptions, publish: &PublishOpt, no_sdist: bool) -> Result<(), Error> {
@@ -417,17 +487,18 @@ fn run() -> Result<(), Error> {
         } => {
             upload_ui(build, &publish, no_sdist)?;
         }
-        Opt::ListPython => {
+        Opt::ListPython { refresh } => {
             let target = Target::from_target_triple(None)?;
-            let found = PythonInterpreter::find_all(&target)?;
+            let found = PythonInterpreter::find_all_with_source(&target, refresh)?;
             println!("🐍 {} python interpreter found:", found.len());
-            for interpreter in found {
-                println!(" - {}", interpreter);
+            for (interpreter, source) in found {
+                println!(" - {} (found in {})", interpreter, source);
             }
         }
         Opt::Develop {
             binding_crate,
             manifest_path,
+            package,
             cargo_extra_args,
             rustc_extra_args,
             release,
@@ -443,6 +514,7 @@ fn run() -> Result<(), Error> {
             develop(
                 binding_crate,
                 &manifest_path,
+                package,
                 cargo_extra_args,
                 rustc_extra_args,
                 &venv_dir,
@@ -478,6 +550,23 @@ fn run() -> Result<(), Error> {
             source_distribution(&wheel_dir, &metadata21, &manifest_path)
                 .context("Failed to build source distribution")?;
         }
+        #[cfg(feature = "auditwheel")]
+        Opt::Audit {
+            wheel,
+            json,
+            manylinux_policy,
+        } => {
+            let policies = match manylinux_policy {
+                Some(path) => Policy::from_file(&path)?,
+                None => Policy::embedded(),
+            };
+            let audit = audit_wheel(&wheel, &policies)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&audit)?);
+            } else {
+                print_audit(&wheel, &audit);
+            }
+        }
         Opt::PEP517(subcommand) => pep517(subcommand)?,
     }
 
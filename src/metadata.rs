@@ -219,6 +219,26 @@ impl Metadata21 {
         out
     }
 
+    /// Adds an extra to `Provides-Extra` and its requirements to `Requires-Dist`, where they get
+    /// an `extra == "<name>"` marker, e.g. `numpy>=1.16; extra == "numpy"`
+    pub fn add_extra(&mut self, extra: &str, requirements: &[String]) {
+        if !self.provides_extra.iter().any(|provided| provided == extra) {
+            self.provides_extra.push(extra.to_string());
+        }
+        for requirement in requirements {
+            let requirement = match requirement.split_once(';') {
+                Some((requirement, marker)) => format!(
+                    "{}; ({}) and extra == \"{}\"",
+                    requirement.trim(),
+                    marker.trim(),
+                    extra
+                ),
+                None => format!("{}; extra == \"{}\"", requirement.trim(), extra),
+            };
+            self.requires_dist.push(requirement);
+        }
+    }
+
     /// Returns the distribution name according to PEP 427, Section "Escaping
     /// and Unicode"
     pub fn get_distribution_escaped(&self) -> String {
@@ -331,4 +351,42 @@ mod test {
             PathBuf::from("info_project-0.1.0.dist-info")
         )
     }
+
+    #[test]
+    fn test_add_extra() {
+        let cargo_toml: CargoToml = toml::from_str(indoc!(
+            r#"
+            [package]
+            authors = ["konstin <konstin@mailbox.org>"]
+            name = "info-project"
+            version = "0.1.0"
+
+            [package.metadata.pyo3-pack]
+            requires-dist = ["toml==0.10.0"]
+            provides-extra = ["numpy"]
+        "#
+        ))
+        .unwrap();
+        let mut metadata = Metadata21::from_cargo_toml(&cargo_toml, Path::new(".")).unwrap();
+
+        metadata.add_extra("numpy", &["numpy>=1.16".to_string()]);
+        metadata.add_extra(
+            "async",
+            &[
+                "trio".to_string(),
+                "asyncio; python_version<'3.4'".to_string(),
+            ],
+        );
+
+        assert_eq!(metadata.provides_extra, vec!["numpy", "async"]);
+        assert_eq!(
+            metadata.requires_dist,
+            vec![
+                "toml==0.10.0",
+                r#"numpy>=1.16; extra == "numpy""#,
+                r#"trio; extra == "async""#,
+                r#"asyncio; (python_version<'3.4') and extra == "async""#,
+            ]
+        );
+    }
 }
@@ -435,15 +435,23 @@ recompiler.make_py_source(ffi, "ffi", r"{ffi_py}")
 }
 
 /// Copies the shared library into the module, which is the only extra file needed with bindings
+///
+/// Without a python interpreter, the library is named for the stable abi (abi3), i.e.
+/// `<module>.abi3.so` or `<module>.pyd` on windows
 pub fn write_bindings_module(
     writer: &mut impl ModuleWriter,
     project_layout: &ProjectLayout,
     module_name: &str,
     artifact: &Path,
-    python_interpreter: &PythonInterpreter,
+    python_interpreter: Option<&PythonInterpreter>,
+    target: &Target,
     develop: bool,
 ) -> Result<(), Error> {
-    let so_filename = python_interpreter.get_library_name(&module_name);
+    let so_filename = match python_interpreter {
+        Some(python_interpreter) => python_interpreter.get_library_name(&module_name),
+        None if target.is_windows() => format!("{}.pyd", module_name),
+        None => format!("{}.abi3.so", module_name),
+    };
 
     match project_layout {
         ProjectLayout::Mixed(ref python_module) => {
@@ -529,6 +537,27 @@ pub fn write_bin(
     Ok(())
 }
 
+/// Libraries bundled into a wheel as pairs of mangled soname and content
+pub type BundledLibraries = Vec<(String, Vec<u8>)>;
+
+/// Adds the libraries bundled by `--manylinux=<policy>-repair` to a `<module name>.libs` directory
+/// at the root of the wheel. Nothing is written if there are no libraries to bundle
+pub fn write_bundled_libraries(
+    writer: &mut impl ModuleWriter,
+    module_name: &str,
+    libraries: &[(String, Vec<u8>)],
+) -> Result<(), Error> {
+    if libraries.is_empty() {
+        return Ok(());
+    }
+    let libs_dir = PathBuf::from(format!("{}.libs", module_name));
+    writer.add_directory(&libs_dir)?;
+    for (soname, content) in libraries {
+        writer.add_bytes_with_permissions(libs_dir.join(soname), content, 0o755)?;
+    }
+    Ok(())
+}
+
 /// Adds the python part of a mixed project to the writer,
 /// excluding older versions of the native library or generated cffi declarations
 pub fn write_python_part(
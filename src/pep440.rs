@@ -0,0 +1,307 @@
+use failure::{bail, format_err, Error};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// The comparison operators of PEP 440
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Operator {
+    /// `~=`
+    Compatible,
+    /// `==`, or `==X.Y.*` with `wildcard`
+    Equal,
+    /// `!=`, or `!=X.Y.*` with `wildcard`
+    NotEqual,
+    /// `<=`
+    LessEqual,
+    /// `>=`
+    GreaterEqual,
+    /// `<`
+    Less,
+    /// `>`
+    Greater,
+    /// `===`
+    Arbitrary,
+}
+
+/// A single clause such as `>=3.6` or `!=3.7.*`
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct VersionSpecifier {
+    operator: Operator,
+    /// The release segment of the version, e.g. `[3, 7]` for `3.7`
+    release: Vec<u64>,
+    /// Whether the version ended with `.*`
+    wildcard: bool,
+    /// The version as written, for `===`
+    version: String,
+}
+
+/// Compares two release segments, where missing parts count as zero, so `3.7 == 3.7.0`
+fn compare_release(left: &[u64], right: &[u64]) -> Ordering {
+    let len = left.len().max(right.len());
+    let padded = |release: &[u64], i| release.get(i).cloned().unwrap_or(0);
+    (0..len)
+        .map(|i| padded(left, i).cmp(&padded(right, i)))
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Returns whether the release starts with the prefix, with missing parts counting as zero
+fn matches_prefix(release: &[u64], prefix: &[u64]) -> bool {
+    compare_release(&release[..release.len().min(prefix.len())], prefix) == Ordering::Equal
+}
+
+impl VersionSpecifier {
+    fn contains(&self, release: &[u64]) -> bool {
+        let ordering = compare_release(release, &self.release);
+        match self.operator {
+            Operator::Compatible => {
+                let prefix = &self.release[..self.release.len() - 1];
+                ordering != Ordering::Less && matches_prefix(release, prefix)
+            }
+            Operator::Equal if self.wildcard => matches_prefix(release, &self.release),
+            Operator::Equal => ordering == Ordering::Equal,
+            Operator::NotEqual if self.wildcard => !matches_prefix(release, &self.release),
+            Operator::NotEqual => ordering != Ordering::Equal,
+            Operator::LessEqual => ordering != Ordering::Greater,
+            Operator::GreaterEqual => ordering != Ordering::Less,
+            Operator::Less => ordering == Ordering::Less,
+            Operator::Greater => ordering == Ordering::Greater,
+            Operator::Arbitrary => {
+                let release: Vec<String> = release.iter().map(ToString::to_string).collect();
+                release.join(".") == self.version
+            }
+        }
+    }
+
+    /// Returns whether some `major.minor.z` matches the specifier, for when the micro version
+    /// is unknown
+    fn contains_any_micro(&self, major: u64, minor: u64) -> bool {
+        let release = [major, minor];
+        // The first two parts of the specifier's version, padded with zeros
+        let prefix = &self.release[..self.release.len().min(2)];
+        match self.operator {
+            // The micro version can always be large enough
+            Operator::Compatible | Operator::GreaterEqual | Operator::Greater => {
+                self.contains(&[major, minor, u64::MAX])
+            }
+            Operator::LessEqual | Operator::Less => self.contains(&[major, minor, 0]),
+            Operator::Equal if self.wildcard && self.release.len() <= 2 => {
+                matches_prefix(&release, &self.release)
+            }
+            Operator::Equal => compare_release(&release, prefix) == Ordering::Equal,
+            Operator::NotEqual if self.wildcard && self.release.len() <= 2 => {
+                !matches_prefix(&release, &self.release)
+            }
+            // Only excludes some of the micro versions
+            Operator::NotEqual => true,
+            Operator::Arbitrary => {
+                let version = format!("{}.{}", major, minor);
+                self.version == version || self.version.starts_with(&format!("{}.", version))
+            }
+        }
+    }
+}
+
+impl FromStr for VersionSpecifier {
+    type Err = Error;
+
+    fn from_str(specifier: &str) -> Result<Self, Error> {
+        let specifier = specifier.trim();
+        // The longer operators must be tried first
+        let operators = [
+            ("===", Operator::Arbitrary),
+            ("~=", Operator::Compatible),
+            ("==", Operator::Equal),
+            ("!=", Operator::NotEqual),
+            ("<=", Operator::LessEqual),
+            (">=", Operator::GreaterEqual),
+            ("<", Operator::Less),
+            (">", Operator::Greater),
+        ];
+        let (operator, version) = operators
+            .iter()
+            .find(|(prefix, _)| specifier.starts_with(prefix))
+            .map(|(prefix, operator)| (*operator, specifier[prefix.len()..].trim()))
+            .ok_or_else(|| {
+                format_err!("'{}' doesn't start with a comparison operator", specifier)
+            })?;
+
+        if operator == Operator::Arbitrary {
+            return Ok(VersionSpecifier {
+                operator,
+                release: Vec::new(),
+                wildcard: false,
+                version: version.to_string(),
+            });
+        }
+
+        let (release, wildcard) = match version.strip_suffix(".*") {
+            Some(release) => (release, true),
+            None => (version, false),
+        };
+        if wildcard && operator != Operator::Equal && operator != Operator::NotEqual {
+            bail!("'{}': only == and != can be used with .*", specifier);
+        }
+        let release = release
+            .split('.')
+            .map(|part| part.parse::<u64>())
+            .collect::<Result<Vec<u64>, _>>()
+            .map_err(|_| {
+                format_err!(
+                    "'{}': only versions consisting of numbers are supported",
+                    specifier
+                )
+            })?;
+        if operator == Operator::Compatible && release.len() < 2 {
+            bail!(
+                "'{}': ~= needs a version with at least two parts",
+                specifier
+            );
+        }
+
+        Ok(VersionSpecifier {
+            operator,
+            release,
+            wildcard,
+            version: version.to_string(),
+        })
+    }
+}
+
+/// A PEP 440 version specifier set such as `>=3.6, !=3.7.*` as used for `Requires-Python`.
+///
+/// Only the release segment of versions is supported, i.e. pre-, post- and dev-releases and local
+/// versions are rejected
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VersionSpecifiers {
+    specifiers: Vec<VersionSpecifier>,
+    text: String,
+}
+
+impl VersionSpecifiers {
+    /// Returns whether the python version matches all specifiers. If the micro version is
+    /// unknown, each specifier only needs to match some `major.minor.z`, so that e.g. `>=3.6.1`
+    /// doesn't exclude a 3.6 interpreter from a sysconfig file
+    pub fn contains(&self, major: usize, minor: usize, micro: Option<usize>) -> bool {
+        let (major, minor) = (major as u64, minor as u64);
+        self.specifiers.iter().all(|specifier| match micro {
+            Some(micro) => specifier.contains(&[major, minor, micro as u64]),
+            None => specifier.contains_any_micro(major, minor),
+        })
+    }
+}
+
+impl FromStr for VersionSpecifiers {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Error> {
+        let specifiers = text
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<VersionSpecifier>, Error>>()?;
+        Ok(VersionSpecifiers {
+            specifiers,
+            text: text.trim().to_string(),
+        })
+    }
+}
+
+impl fmt::Display for VersionSpecifiers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Checks `major.minor.0`
+    fn contains(specifiers: &str, major: usize, minor: usize) -> bool {
+        specifiers
+            .parse::<VersionSpecifiers>()
+            .unwrap()
+            .contains(major, minor, Some(0))
+    }
+
+    fn contains_micro(specifiers: &str, major: usize, minor: usize, micro: Option<usize>) -> bool {
+        specifiers
+            .parse::<VersionSpecifiers>()
+            .unwrap()
+            .contains(major, minor, micro)
+    }
+
+    #[test]
+    fn test_comparisons() {
+        assert!(contains(">=3.6", 3, 6));
+        assert!(contains(">=3.6", 3, 10));
+        assert!(!contains(">=3.6", 3, 5));
+        assert!(contains(">3.6", 3, 7));
+        assert!(!contains(">3.6", 3, 6));
+        assert!(contains("<3.8", 3, 7));
+        assert!(!contains("<3.8", 3, 8));
+        assert!(contains("<=3.8", 3, 8));
+        assert!(contains("==3.7", 3, 7));
+        assert!(contains("==3.7.0", 3, 7));
+        assert!(!contains("==3.7.1", 3, 7));
+        assert!(contains("!=3.7", 3, 8));
+        assert!(contains("===3.7.0", 3, 7));
+        assert!(!contains("===3.7", 3, 7));
+    }
+
+    #[test]
+    fn test_wildcards_and_compatible() {
+        assert!(contains("==3.*", 3, 9));
+        assert!(!contains("==3.*", 4, 0));
+        assert!(!contains("!=3.7.*", 3, 7));
+        assert!(contains("!=3.7.*", 3, 8));
+        assert!(contains("~=3.6", 3, 9));
+        assert!(!contains("~=3.6", 3, 5));
+        assert!(!contains("~=3.6", 4, 0));
+        assert!(contains("~=3.6.0", 3, 6));
+        assert!(!contains("~=3.6.0", 3, 7));
+    }
+
+    #[test]
+    fn test_micro_versions() {
+        assert!(contains_micro(">=3.6.1", 3, 6, Some(9)));
+        assert!(!contains_micro(">=3.6.1", 3, 6, Some(0)));
+        assert!(contains_micro("==3.6.*", 3, 6, Some(9)));
+        assert!(!contains_micro("<3.6.1", 3, 6, Some(1)));
+        // Without the micro version, some 3.6.z must be able to match
+        assert!(contains_micro(">=3.6.1", 3, 6, None));
+        assert!(!contains_micro(">=3.6.1", 3, 5, None));
+        assert!(contains_micro("<3.6.1", 3, 6, None));
+        assert!(!contains_micro("<3.6", 3, 6, None));
+        assert!(contains_micro("==3.6.1", 3, 6, None));
+        assert!(!contains_micro("==3.6.1", 3, 7, None));
+        assert!(contains_micro("!=3.6.1", 3, 6, None));
+        assert!(!contains_micro("!=3.6.*", 3, 6, None));
+        assert!(contains_micro("~=3.6.1", 3, 6, None));
+        assert!(contains_micro("===3.6.1", 3, 6, None));
+        assert!(contains_micro(">=3.6.1, <3.6.5", 3, 6, None));
+    }
+
+    #[test]
+    fn test_specifier_sets() {
+        assert!(contains(">=3.6, <4", 3, 8));
+        assert!(contains(" >= 3.5 , != 3.6.*", 3, 7));
+        assert!(!contains(">=3.5,!=3.6.*", 3, 6));
+        assert!(!contains(">=3.6,<3.8", 3, 8));
+        assert_eq!(
+            ">=3.6, <4"
+                .parse::<VersionSpecifiers>()
+                .unwrap()
+                .to_string(),
+            ">=3.6, <4"
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        for invalid in &["3.6", ">=3.6b1", ">=3.*", "~=3", ">=3.6,", ""] {
+            assert!(invalid.parse::<VersionSpecifiers>().is_err(), "{}", invalid);
+        }
+    }
+}
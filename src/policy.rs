@@ -0,0 +1,138 @@
+use failure::{format_err, Error, ResultExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The policies that are used unless `--manylinux-policy` is given, in the format of auditwheel's
+/// policy.json
+const EMBEDDED_POLICIES: &str = include_str!("policy.json");
+
+/// Whether a failed check fails the build or only prints a warning
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckLevel {
+    /// Print a warning
+    Warn,
+    /// Fail with an error
+    #[default]
+    Error,
+}
+
+/// A platform policy such as manylinux2010, in the same shape as an entry of auditwheel's
+/// policy.json
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct Policy {
+    /// The name of the policy, which is also the platform tag without the architecture, e.g.
+    /// `manylinux2010`
+    pub name: String,
+    /// Older and therefore stricter policies have a higher priority
+    pub priority: i64,
+    /// Maps each architecture to the symbol version names and their allowed versions, e.g.
+    /// `x86_64` to `GLIBC` to `["2.2.5", ..., "2.12"]`. An empty list forbids all versions of
+    /// that name
+    pub symbol_versions: HashMap<String, HashMap<String, Vec<String>>>,
+    /// The libraries which may be linked dynamically
+    pub lib_whitelist: Vec<String>,
+    /// How to handle RPATH or RUNPATH entries that are absolute or point outside of the wheel.
+    /// Not part of auditwheel's format, defaults to `error`
+    #[serde(default)]
+    pub rpath: CheckLevel,
+    /// How to handle libraries that require an executable stack. Not part of auditwheel's format,
+    /// defaults to `error`
+    #[serde(default)]
+    pub executable_stack: CheckLevel,
+}
+
+impl Policy {
+    /// Returns the policies that ship with pyo3-pack
+    pub fn embedded() -> Vec<Policy> {
+        serde_json::from_str(EMBEDDED_POLICIES).expect("The embedded policy.json is invalid ಠ_ಠ")
+    }
+
+    /// Reads a policy file with the same format as auditwheel's policy.json, which replaces the
+    /// embedded policies
+    pub fn from_file(path: &Path) -> Result<Vec<Policy>, Error> {
+        let contents = fs::read_to_string(path)
+            .context(format!("Can't read the policy file at {}", path.display()))?;
+        let policies = serde_json::from_str(&contents)
+            .context(format!("{} is not a valid policy file", path.display()))?;
+        Ok(policies)
+    }
+
+    /// Returns the policy with the given name
+    pub fn find<'a>(policies: &'a [Policy], name: &str) -> Result<&'a Policy, Error> {
+        policies
+            .iter()
+            .find(|policy| policy.name == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = policies.iter().map(|policy| policy.name.as_str()).collect();
+                format_err!(
+                    "There is no {} policy, the known policies are: {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+
+    /// Returns the symbol version names and the allowed versions for an architecture, e.g.
+    /// `x86_64`
+    pub fn symbol_versions_for(&self, arch: &str) -> Option<&HashMap<String, Vec<String>>> {
+        self.symbol_versions.get(arch)
+    }
+
+    /// Returns whether the policy is for musl instead of glibc, which is decided by the name as
+    /// in PEP 656, e.g. `musllinux_1_1`
+    pub fn is_musl(&self) -> bool {
+        self.name.starts_with("musllinux")
+    }
+
+    /// Returns whether the policy defines the allowed symbol versions for an architecture, i.e.
+    /// whether there is a platform tag such as `manylinux2014_aarch64`
+    pub fn supports_arch(&self, arch: &str) -> bool {
+        self.symbol_versions.contains_key(arch)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_embedded_policies() {
+        let policies = Policy::embedded();
+        let manylinux2010 = Policy::find(&policies, "manylinux2010").unwrap();
+        assert_eq!(manylinux2010.priority, 90);
+        assert!(manylinux2010
+            .lib_whitelist
+            .contains(&"libpthread.so.0".to_string()));
+        assert_eq!(
+            manylinux2010.symbol_versions_for("x86_64").unwrap()["GLIBC"].last(),
+            Some(&"2.12".to_string())
+        );
+        assert!(Policy::find(&policies, "manylinux1").unwrap().priority > manylinux2010.priority);
+        assert!(Policy::find(&policies, "manylinux3000").is_err());
+        let manylinux2014 = Policy::find(&policies, "manylinux2014").unwrap();
+        for arch in &["aarch64", "armv7l", "ppc64le", "s390x"] {
+            assert!(manylinux2014.supports_arch(arch));
+            assert!(!manylinux2010.supports_arch(arch));
+        }
+        let musllinux = Policy::find(&policies, "musllinux_1_1").unwrap();
+        assert!(musllinux.is_musl());
+        assert!(!manylinux2014.is_musl());
+        assert!(musllinux
+            .lib_whitelist
+            .contains(&"libc.musl-x86_64.so.1".to_string()));
+        assert!(!musllinux.lib_whitelist.contains(&"libc.so.6".to_string()));
+    }
+
+    #[test]
+    fn test_auditwheel_format() {
+        let policies: Vec<Policy> = serde_json::from_str(
+            r#"[{"name": "manylinux1", "priority": 100, "symbol_versions": {}, "lib_whitelist": []}]"#,
+        )
+        .unwrap();
+        assert_eq!(policies[0].rpath, CheckLevel::Error);
+        assert_eq!(policies[0].executable_stack, CheckLevel::Error);
+    }
+}
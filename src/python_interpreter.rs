@@ -1,11 +1,14 @@
+use crate::interpreter_cache::InterpreterCache;
 use crate::Manylinux;
 use crate::Target;
 use failure::{bail, format_err, Error, Fail, ResultExt};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::fmt;
+use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
@@ -82,7 +85,7 @@ fn windows_interpreter_no_build(
 /// As well as the version numbers, etc. of the interpreters we also have to find the
 /// pointer width to make sure that the pointer width (32-bit or 64-bit) matches across
 /// platforms.
-fn find_all_windows(target: &Target) -> Result<Vec<String>, Error> {
+fn find_all_windows(target: &Target) -> Result<Vec<(PathBuf, InterpreterSource)>, Error> {
     let code = "import sys; print(sys.executable or '')";
     let mut interpreter = vec![];
     let mut versions_found = HashSet::new();
@@ -136,7 +139,7 @@ fn find_all_windows(target: &Target) -> Result<Vec<String>, Error> {
                     if !output.status.success() || path.trim().is_empty() {
                         bail!("Couldn't determine the path to python for `py {}`", version);
                     }
-                    interpreter.push(path.to_string());
+                    interpreter.push((PathBuf::from(path), InterpreterSource::PyLauncher));
                     versions_found.insert((major, minor));
                 }
             }
@@ -204,7 +207,7 @@ fn find_all_windows(target: &Target) -> Result<Vec<String>, Error> {
                         continue;
                     }
 
-                    interpreter.push(String::from(executable.to_str().unwrap()));
+                    interpreter.push((executable, InterpreterSource::Conda));
                     versions_found.insert((major, minor));
                 }
             }
@@ -218,23 +221,187 @@ fn find_all_windows(target: &Target) -> Result<Vec<String>, Error> {
     Ok(interpreter)
 }
 
-/// Since there is no known way to list the installed python versions on unix
-/// (or just generally to list all binaries in $PATH, which could then be
-/// filtered down), this is a workaround (which works until python 4 is
-/// released, which won't be too soon)
-fn find_all_unix() -> Vec<String> {
-    let interpreter = &[
-        "python3.5",
-        "python3.6",
-        "python3.7",
-        "python3.8",
-        "python3.9",
-    ];
-
-    interpreter.iter().map(ToString::to_string).collect()
+/// Returns whether the file name is one of a python 3 binary, e.g. `python3`, `python3.10` or
+/// `pypy3.6`
+fn is_python3_binary(name: &str) -> bool {
+    Regex::new(r"^(python|pypy)3(\.\d+)?$")
+        .unwrap()
+        .is_match(name)
 }
 
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path)
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Returns the `bin` directories of all subdirectories, e.g. `~/.pyenv/versions/*/bin` for
+/// `~/.pyenv/versions`
+fn bin_dirs_in(dir: &Path) -> Vec<PathBuf> {
+    let mut bin_dirs: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .map(|path| path.join("bin"))
+            .collect(),
+        Err(_) => return Vec::new(),
+    };
+    bin_dirs.sort();
+    bin_dirs
+}
+
+/// Lists the python 3 binaries in each directory, sorted by name. Binaries that resolve to the
+/// same file, e.g. `python3` and `python3.7` when one is a symlink to the other, are only
+/// returned the first time.
+fn find_binaries(dirs: &[(PathBuf, InterpreterSource)]) -> Vec<(PathBuf, InterpreterSource)> {
+    let mut resolved_binaries = HashSet::new();
+    let mut binaries = Vec::new();
+    for (dir, source) in dirs {
+        let mut in_dir: Vec<PathBuf> = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_name().to_str().is_some_and(is_python3_binary))
+                .map(|entry| entry.path())
+                .filter(|path| is_executable(path))
+                .collect(),
+            Err(_) => continue,
+        };
+        in_dir.sort();
+        for binary in in_dir {
+            let resolved = fs::canonicalize(&binary).unwrap_or_else(|_| binary.clone());
+            if resolved_binaries.insert(resolved) {
+                binaries.push((binary, *source));
+            }
+        }
+    }
+    binaries
+}
+
+/// There is no registry of the installed python versions on unix, so we look for binaries named
+/// like `python3.7` or `pypy3` in:
+///
+///  - all directories in PATH, except for pyenv's shims, which fail for versions that aren't
+///    activated
+///  - `$PYENV_ROOT/versions/*/bin`, with `~/.pyenv` as default for `PYENV_ROOT`
+///  - `$CONDA_PREFIX` and the `anaconda3`, `miniconda`, `miniconda3` and `miniforge3`
+///    installations in the home directory, each with their environments in `envs/*`
+fn find_all_unix() -> Vec<(PathBuf, InterpreterSource)> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let pyenv_root = env::var_os("PYENV_ROOT")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|home| home.join(".pyenv")));
+
+    let mut dirs = Vec::new();
+    if let Some(path) = env::var_os("PATH") {
+        for dir in env::split_paths(&path) {
+            if let Some(ref pyenv_root) = pyenv_root {
+                if dir == pyenv_root.join("shims") {
+                    continue;
+                }
+            }
+            dirs.push((dir, InterpreterSource::Path));
+        }
+    }
+
+    if let Some(ref pyenv_root) = pyenv_root {
+        for dir in bin_dirs_in(&pyenv_root.join("versions")) {
+            dirs.push((dir, InterpreterSource::Pyenv));
+        }
+    }
+
+    let mut conda_roots: Vec<PathBuf> = env::var_os("CONDA_PREFIX")
+        .map(PathBuf::from)
+        .into_iter()
+        .collect();
+    if let Some(ref home) = home {
+        for name in &["anaconda3", "miniconda", "miniconda3", "miniforge3"] {
+            conda_roots.push(home.join(name));
+        }
+    }
+    for root in conda_roots {
+        dirs.push((root.join("bin"), InterpreterSource::Conda));
+        for dir in bin_dirs_in(&root.join("envs")) {
+            dirs.push((dir, InterpreterSource::Conda));
+        }
+    }
+
+    find_binaries(&dirs)
+}
+
+/// A version with an optional implementation that is given with `-i` instead of an executable,
+/// e.g. `3.7` or `pypy3.6`
 #[derive(Debug, Clone, Eq, PartialEq)]
+pub struct InterpreterSelector {
+    interpreter: Interpreter,
+    major: usize,
+    minor: usize,
+}
+
+impl InterpreterSelector {
+    /// Parses `3.7`, `cpython3.7` or `pypy3.6`, where a version without an implementation means
+    /// CPython. Returns `None` for everything else, e.g. `python3.7` or a path
+    pub fn parse(value: &str) -> Option<InterpreterSelector> {
+        let capture = Regex::new(r"^(cpython|pypy)?(\d+)\.(\d+)$")
+            .unwrap()
+            .captures(value)?;
+        let interpreter = match capture.get(1).map(|x| x.as_str()) {
+            Some("pypy") => Interpreter::PyPy,
+            _ => Interpreter::CPython,
+        };
+        Some(InterpreterSelector {
+            interpreter,
+            major: capture[2].parse().ok()?,
+            minor: capture[3].parse().ok()?,
+        })
+    }
+
+    /// Returns whether the interpreter has the selected implementation and version
+    pub fn matches(&self, interpreter: &PythonInterpreter) -> bool {
+        interpreter.interpreter == self.interpreter
+            && interpreter.major == self.major
+            && interpreter.minor == self.minor
+    }
+}
+
+impl fmt::Display for InterpreterSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}.{}", self.interpreter, self.major, self.minor)
+    }
+}
+
+/// Where an interpreter was found, which is shown by `list-python`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum InterpreterSource {
+    /// A directory in PATH
+    Path,
+    /// A version installed with pyenv
+    Pyenv,
+    /// A conda installation or environment
+    Conda,
+    /// The python launcher for windows, `py`
+    PyLauncher,
+}
+
+impl fmt::Display for InterpreterSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InterpreterSource::Path => write!(f, "PATH"),
+            InterpreterSource::Pyenv => write!(f, "pyenv"),
+            InterpreterSource::Conda => write!(f, "conda"),
+            InterpreterSource::PyLauncher => write!(f, "py launcher"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum Interpreter {
     CPython,
     PyPy,
@@ -250,10 +417,11 @@ impl fmt::Display for Interpreter {
 }
 
 /// The output format of [GET_INTERPRETER_METADATA]
-#[derive(Serialize, Deserialize)]
-struct IntepreterMetadataMessage {
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct IntepreterMetadataMessage {
     major: usize,
     minor: usize,
+    micro: Option<usize>,
     abiflags: Option<String>,
     interpreter: String,
     ext_suffix: Option<String>,
@@ -261,6 +429,7 @@ struct IntepreterMetadataMessage {
     u: bool,
     d: bool,
     platform: String,
+    sysconfig_platform: String,
     abi_tag: Option<String>,
 }
 
@@ -271,14 +440,17 @@ pub struct PythonInterpreter {
     pub major: usize,
     /// Python's minor version
     pub minor: usize,
+    /// Python's micro version, which is unknown for interpreters from sysconfig files that only
+    /// contain `major.minor`
+    pub micro: Option<usize>,
     /// For linux and mac, this contains the value of the abiflags, e.g. "m"
-    /// for python3.5m or "dm" for python3.6dm. On windows, the value is
-    /// always "".
+    /// for python3.5m or "dm" for python3.6dm. Since python 3.8, it is "" or
+    /// "d" for debug builds. On windows, the value is always "".
     ///
     /// See PEP 261 and PEP 393 for details
     pub abiflags: String,
-    /// Currently just the value of [Target::os()], i.e. "windows", "linux" or
-    /// "macos"
+    /// The target of the interpreter, which on the bsds also contains the release from
+    /// `sysconfig.get_platform()`
     pub target: Target,
     /// Path to the python interpreter, e.g. /usr/bin/python3.6
     ///
@@ -292,13 +464,152 @@ pub struct PythonInterpreter {
     ///
     /// Note that this always `None` on windows
     pub abi_tag: Option<String>,
+    /// Set if the interpreter was read from a sysconfig file instead of running it, which means
+    /// that pyo3 can't run it either and needs the `PYO3_CROSS_*` environment variables
+    pub cross_compile: Option<CrossCompileInfo>,
+}
+
+/// The locations of a python installation for another target, which pyo3's build script gets
+/// through `PYO3_CROSS_LIB_DIR` and `PYO3_CROSS_INCLUDE_DIR`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CrossCompileInfo {
+    /// The directory with the libpython and the `_sysconfigdata_*.py` of the target
+    pub lib_dir: PathBuf,
+    /// The directory with the `Python.h` of the target, if the sysconfig contains it
+    pub include_dir: Option<PathBuf>,
+}
+
+/// Reads the output of `python -m sysconfig`, i.e. the paths and variables such as
+/// `\tABIFLAGS = "m"`, as well as the `Platform` and `Python version` headers
+fn parse_sysconfig_dump(contents: &str) -> HashMap<String, String> {
+    let mut variables = HashMap::new();
+    for line in contents.lines() {
+        let (name, value) = if line.starts_with('\t') {
+            match line.find(" = ") {
+                Some(pos) => (line[1..pos].to_string(), &line[pos + 3..]),
+                None => continue,
+            }
+        } else {
+            match line.find(": ") {
+                Some(pos) => (line[..pos].to_string(), &line[pos + 2..]),
+                None => continue,
+            }
+        };
+        variables.insert(name, value.trim().trim_matches('"').to_string());
+    }
+    variables
+}
+
+/// Reads the `build_time_vars` of a `_sysconfigdata_*.py` file. Strings split over multiple
+/// lines are joined and integers are converted to strings, everything else is skipped
+fn parse_sysconfigdata(contents: &str) -> HashMap<String, String> {
+    let string = r#"'(?:[^'\\]|\\.)*'|"(?:[^"\\]|\\.)*""#;
+    let entry = Regex::new(&format!(
+        r#"['"](\w+)['"]:\s*((?:(?:{})\s*)+|-?\d+)[,}}]"#,
+        string
+    ))
+    .unwrap();
+    let string = Regex::new(string).unwrap();
+    entry
+        .captures_iter(contents)
+        .map(|capture| {
+            let value = &capture[2];
+            let value = if value.starts_with('\'') || value.starts_with('"') {
+                string
+                    .find_iter(value)
+                    .map(|part| &part.as_str()[1..part.as_str().len() - 1])
+                    .collect()
+            } else {
+                value.to_string()
+            };
+            (capture[1].to_string(), value)
+        })
+        .collect()
+}
+
+/// Assembles what get_interpreter_metadata.py would print from the sysconfig variables of an
+/// interpreter
+fn metadata_from_sysconfig(
+    variables: &HashMap<String, String>,
+) -> Result<IntepreterMetadataMessage, Error> {
+    let var = |name: &str| variables.get(name).cloned();
+    // On windows, VERSION is e.g. "37" instead of "3.7"
+    let version = var("py_version_short")
+        .or_else(|| var("Python version"))
+        .or_else(|| var("VERSION"))
+        .ok_or_else(|| format_err!("The sysconfig doesn't contain the python version"))?;
+    let mut parts = version.splitn(2, '.').map(str::parse::<usize>);
+    let (major, minor) = match (parts.next(), parts.next()) {
+        (Some(Ok(major)), Some(Ok(minor))) => (major, minor),
+        _ => bail!("{} is not a valid python version", version),
+    };
+
+    // Only some sysconfig dumps contain the full version, e.g. py_version = "2.7.15rc1"
+    let micro = var("py_version").and_then(|version| {
+        let micro = version.split('.').nth(2)?;
+        let digits = micro
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(micro.len());
+        micro[..digits].parse().ok()
+    });
+
+    let soabi = var("SOABI").unwrap_or_default();
+    let interpreter = if soabi.starts_with("pypy") {
+        "pypy"
+    } else {
+        "cpython"
+    };
+
+    let host_gnu_type = var("HOST_GNU_TYPE").unwrap_or_default();
+    let machdep = var("MACHDEP");
+    // `_sysconfigdata_*.py` has no platform, but on the bsds the release is e.g. in
+    // HOST_GNU_TYPE = "amd64-portbld-freebsd12.0"
+    let sysconfig_platform = match (var("Platform"), machdep.as_ref()) {
+        (Some(platform), _) => platform,
+        (None, Some(machdep)) => {
+            let os = machdep.trim_end_matches(|c: char| c.is_ascii_digit());
+            let release = host_gnu_type
+                .rsplit('-')
+                .next()
+                .filter(|system| system.starts_with(os))
+                .map(|system| &system[os.len()..])
+                .unwrap_or_default();
+            format!("{}-{}", os, release)
+        }
+        (None, None) => bail!("The sysconfig doesn't contain the platform"),
+    };
+    // MACHDEP is sys.platform, but it's missing on windows and for pypy
+    let platform = match machdep {
+        Some(machdep) => machdep,
+        None => match sysconfig_platform.split('-').next().unwrap_or_default() {
+            "win" | "win32" => "win32".to_string(),
+            "macosx" => "darwin".to_string(),
+            os => os.to_string(),
+        },
+    };
+
+    Ok(IntepreterMetadataMessage {
+        major,
+        minor,
+        micro,
+        abiflags: var("ABIFLAGS"),
+        interpreter: interpreter.to_string(),
+        ext_suffix: var("EXT_SUFFIX"),
+        m: var("WITH_PYMALLOC") == Some("1".to_string()),
+        u: var("Py_UNICODE_SIZE") == Some("4".to_string()),
+        d: var("Py_DEBUG") == Some("1".to_string()),
+        platform,
+        sysconfig_platform,
+        abi_tag: soabi.split('-').nth(1).map(ToString::to_string),
+    })
 }
 
 /// Returns the abiflags that are assembled through the message, with some
 /// additional sanity checks.
 ///
 /// The rules are as follows:
-///  - python 3 + Unix: Use ABIFLAGS
+///  - python 3.5 to 3.7 + Unix: Use ABIFLAGS, which is "m" or "dm" for debug builds
+///  - python 3.8+ + Unix: Use ABIFLAGS, which is "" or "d" for debug builds
 ///  - python 3 + Windows: No ABIFLAGS, return an empty string
 fn fun_with_abiflags(
     message: &IntepreterMetadataMessage,
@@ -308,6 +619,14 @@ fn fun_with_abiflags(
         "win32" | "win_amd64" => target.is_windows(),
         "linux" | "linux2" | "linux3" => target.is_linux(),
         "darwin" => target.is_macos(),
+        // sys.platform contains the major version on the bsds, e.g. "freebsd12"
+        platform
+            if ["freebsd", "netbsd", "openbsd"]
+                .iter()
+                .any(|bsd| platform.starts_with(bsd)) =>
+        {
+            target.is_bsd()
+        }
         _ => false,
     };
 
@@ -336,8 +655,25 @@ fn fun_with_abiflags(
             Ok("".to_string())
         }
     } else if let Some(ref abiflags) = message.abiflags {
-        if abiflags != "m" {
-            bail!("A python 3 interpreter on linux or mac os must have 'm' as abiflags ಠ_ಠ")
+        if message.minor >= 8 {
+            // pymalloc is no longer part of the abi since 3.8, see https://bugs.python.org/issue36707
+            if !abiflags.is_empty() && abiflags != "d" {
+                bail!(
+                    "A python 3.8+ interpreter on linux or mac os must have '' or 'd' as abiflags, \
+                     but python {}.{} has '{}' ಠ_ಠ",
+                    message.major,
+                    message.minor,
+                    abiflags
+                )
+            }
+        } else if abiflags != "m" && abiflags != "dm" {
+            bail!(
+                "A python 3.5 to 3.7 interpreter on linux or mac os must have 'm' or 'dm' as \
+                 abiflags, but python {}.{} has '{}' ಠ_ಠ",
+                message.major,
+                message.minor,
+                abiflags
+            )
         }
         Ok(abiflags.clone())
     } else if message.interpreter == "pypy" {
@@ -377,31 +713,39 @@ impl PythonInterpreter {
                 }
             }
             Interpreter::PyPy => {
-                // TODO: Find out if PyPy can support manylinux 2010 and/or
-                //       add a `Default` variant to the Manylinux enum
-                if manylinux != &Manylinux::Off {
-                    eprintln!(
-                        "🛈  Note: PyPy doesn't support the manylinux yet, \
-                         so native wheels are built instead of manylinux wheels"
-                    );
-                }
-                // hack to never use manylinux for pypy
-                let platform = self.target.get_platform_tag(&Manylinux::Off);
-                // pypy uses its version as part of the ABI, e.g.
-                // pypy3 v7.1 => pp371-pypy3_71-linux_x86_64.whl
+                // Like packaging.tags, the python tag contains the python version and the abi tag
+                // the pypy version, e.g. pp37-pypy37_pp73-manylinux2010_x86_64
                 format!(
-                    "pp3{abi_tag}-pypy3_{abi_tag}-{platform}",
-                    // TODO: Proper tag handling for pypy
-                    abi_tag = self
-                        .abi_tag
-                        .clone()
-                        .expect("PyPy's syconfig didn't define an `SOABI` ಠ_ಠ"),
-                    platform = platform,
+                    "pp{major}{minor}-{abi}-{platform}",
+                    major = self.major,
+                    minor = self.minor,
+                    abi = self.get_pypy_abi_tag(),
+                    platform = self.target.get_platform_tag(manylinux),
                 )
             }
         }
     }
 
+    /// Returns the abi tag of pypy the way packaging.tags computes it, i.e. the first two parts of
+    /// the EXT_SUFFIX with `_` instead of `-` and `.`, e.g. `pypy37_pp73` for
+    /// `.pypy37-pp73-x86_64-linux-gnu.so` or `pypy3_70` for `.pypy3-70-x86_64-linux-gnu.so`.
+    ///
+    /// Old pypy versions on windows have `.pyd` as EXT_SUFFIX, so we fall back to the SOABI there
+    fn get_pypy_abi_tag(&self) -> String {
+        let from_ext_suffix = self
+            .ext_suffix
+            .as_ref()
+            .and_then(|ext_suffix| ext_suffix.split('.').nth(1))
+            .filter(|soabi| soabi.starts_with("pypy"))
+            .map(|soabi| soabi.splitn(3, '-').take(2).collect::<Vec<_>>().join("-"));
+        let soabi = match (from_ext_suffix, &self.abi_tag) {
+            (Some(soabi), _) => soabi,
+            (None, Some(abi_tag)) => format!("pypy{}{}-{}", self.major, self.minor, abi_tag),
+            (None, None) => format!("pypy{}{}", self.major, self.minor),
+        };
+        soabi.replace(['-', '.'], "_")
+    }
+
     /// Generates the correct suffix for shared libraries and adds it to the base name
     ///
     /// For CPython, generate extensions as follows:
@@ -416,6 +760,9 @@ impl PythonInterpreter {
     /// Linux:   steinlaus.cpython-35m-x86_64-linux-gnu.so
     /// Windows: steinlaus.cp35-win_amd64.pyd
     /// Mac:     steinlaus.cpython-35m-darwin.so
+    /// FreeBSD: steinlaus.cpython-35m.so
+    ///
+    /// Since CPython 3.8, the "m" is gone, e.g. steinlaus.cpython-38-x86_64-linux-gnu.so
     ///
     /// For pypy3, we read sysconfig.get_config_var("EXT_SUFFIX").
     ///
@@ -427,8 +774,10 @@ impl PythonInterpreter {
                 let platform = self.target.get_shared_platform_tag();
 
                 if self.target.is_unix() {
+                    // The bsds don't have a platform in the suffix
+                    let platform = platform.map(|x| format!("-{}", x)).unwrap_or_default();
                     format!(
-                        "{base}.cpython-{major}{minor}{abiflags}-{platform}.so",
+                        "{base}.cpython-{major}{minor}{abiflags}{platform}.so",
                         base = base,
                         major = self.major,
                         minor = self.minor,
@@ -441,7 +790,7 @@ impl PythonInterpreter {
                         base = base,
                         major = self.major,
                         minor = self.minor,
-                        platform = platform
+                        platform = platform.unwrap_or_default()
                     )
                 }
             }
@@ -458,18 +807,55 @@ impl PythonInterpreter {
 
     /// Checks whether the given command is a python interpreter and returns a
     /// [PythonInterpreter] if that is the case
+    ///
+    /// The metadata of the interpreter is cached on disk, so it's only run again if the
+    /// executable changed
     pub fn check_executable(
         executable: impl AsRef<Path>,
         target: &Target,
     ) -> Result<Option<PythonInterpreter>, Error> {
-        let output = Command::new(&executable.as_ref())
+        let mut cache = InterpreterCache::load(false);
+        let interpreter =
+            PythonInterpreter::check_executable_cached(executable.as_ref(), target, &mut cache);
+        cache.save();
+        interpreter
+    }
+
+    /// [PythonInterpreter::check_executable] with a cache that was already loaded
+    fn check_executable_cached(
+        executable: &Path,
+        target: &Target,
+        cache: &mut InterpreterCache,
+    ) -> Result<Option<PythonInterpreter>, Error> {
+        let message = match cache.get(executable) {
+            Some(message) => message,
+            None => match PythonInterpreter::get_metadata_message(executable)? {
+                Some(message) => {
+                    cache.insert(executable, message.clone());
+                    message
+                }
+                None => return Ok(None),
+            },
+        };
+
+        if (message.major == 2 && message.minor != 7) || (message.major == 3 && message.minor < 5) {
+            return Ok(None);
+        }
+
+        PythonInterpreter::from_metadata_message(message, executable, target).map(Some)
+    }
+
+    /// Runs get_interpreter_metadata.py with the executable. Returns `None` if the executable
+    /// doesn't exist
+    fn get_metadata_message(executable: &Path) -> Result<Option<IntepreterMetadataMessage>, Error> {
+        let output = Command::new(executable)
             .args(&["-c", GET_INTERPRETER_METADATA])
             .stderr(Stdio::inherit())
             .output();
 
         let err_msg = format!(
             "Trying to get metadata from the python interpreter '{}' failed",
-            executable.as_ref().display()
+            executable.display()
         );
 
         let output = match output {
@@ -492,10 +878,78 @@ impl PythonInterpreter {
             .context(err_msg)
             .context(String::from_utf8_lossy(&output.stdout).trim().to_string())?;
 
-        if (message.major == 2 && message.minor != 7) || (message.major == 3 && message.minor < 5) {
-            return Ok(None);
+        Ok(Some(message))
+    }
+
+    /// Reads the metadata of an interpreter from a sysconfig file instead of running it, which
+    /// is meant for cross compiling. The file is either the output of `python -m sysconfig`,
+    /// like the files in `sysconfig/`, or the `_sysconfigdata_*.py` of the target's python
+    ///
+    /// `PYO3_CROSS_LIB_DIR` is the directory of `_sysconfigdata_*.py` (or its parent if that is
+    /// `lib/python3.X`) and LIBDIR for the output of `python -m sysconfig`, or `libs` in BINDIR on
+    /// windows
+    pub fn from_sysconfig_file(path: &Path, target: &Target) -> Result<PythonInterpreter, Error> {
+        let contents = fs::read_to_string(path)
+            .context(format!("Can't read the sysconfig at {}", path.display()))?;
+        let is_sysconfigdata = path.extension().is_some_and(|extension| extension == "py");
+        let variables = if is_sysconfigdata {
+            parse_sysconfigdata(&contents)
+        } else {
+            parse_sysconfig_dump(&contents)
+        };
+        let message = metadata_from_sysconfig(&variables)
+            .context(format!("{} is not a valid sysconfig", path.display()))?;
+
+        // The platform of the library name comes from the target, so they must match
+        if let (Some(multiarch), Some(platform)) = (
+            variables.get("MULTIARCH").filter(|x| !x.is_empty()),
+            target.get_shared_platform_tag(),
+        ) {
+            if multiarch != platform {
+                bail!(
+                    "The sysconfig at {} is for {}, but the target is {}",
+                    path.display(),
+                    multiarch,
+                    platform
+                );
+            }
         }
 
+        let lib_dir = if is_sysconfigdata {
+            let dir = path.parent().unwrap_or_else(|| Path::new("."));
+            match dir.file_name().and_then(|name| name.to_str()) {
+                Some(name) if name.starts_with("python") => dir.parent().unwrap_or(dir),
+                _ => dir,
+            }
+            .to_path_buf()
+        } else {
+            // On windows, there's no LIBDIR and pythonXY.lib is in `libs` next to python.exe
+            match (variables.get("LIBDIR"), variables.get("BINDIR")) {
+                (Some(lib_dir), _) => PathBuf::from(lib_dir),
+                (None, Some(bin_dir)) if target.is_windows() => Path::new(bin_dir).join("libs"),
+                _ => bail!("The sysconfig at {} doesn't contain LIBDIR", path.display()),
+            }
+        };
+        let include_dir = variables
+            .get("INCLUDEPY")
+            .or_else(|| variables.get("include"))
+            .map(PathBuf::from);
+
+        let mut interpreter = PythonInterpreter::from_metadata_message(message, path, target)?;
+        interpreter.cross_compile = Some(CrossCompileInfo {
+            lib_dir,
+            include_dir,
+        });
+        Ok(interpreter)
+    }
+
+    /// Checks the metadata from get_interpreter_metadata.py or a sysconfig file and turns it into
+    /// a [PythonInterpreter]
+    fn from_metadata_message(
+        message: IntepreterMetadataMessage,
+        executable: &Path,
+        target: &Target,
+    ) -> Result<PythonInterpreter, Error> {
         let interpreter;
         match message.interpreter.as_str() {
             "cpython" => interpreter = Interpreter::CPython,
@@ -507,39 +961,137 @@ impl PythonInterpreter {
 
         let abiflags = fun_with_abiflags(&message, &target).context(format_err!(
             "Failed to get information from the python interpreter at {}",
-            executable.as_ref().display()
+            executable.display()
         ))?;
 
-        Ok(Some(PythonInterpreter {
+        Ok(PythonInterpreter {
             major: message.major,
             minor: message.minor,
+            micro: message.micro,
             abiflags,
-            target: target.clone(),
-            executable: executable.as_ref().to_path_buf(),
+            target: target.with_sysconfig_platform(&message.sysconfig_platform),
+            executable: executable.to_path_buf(),
             ext_suffix: message.ext_suffix,
             interpreter,
             abi_tag: message.abi_tag,
-        }))
+            cross_compile: None,
+        })
     }
 
     /// Tries to find all installed python versions using the heuristic for the
     /// given platform
     pub fn find_all(target: &Target) -> Result<Vec<PythonInterpreter>, Error> {
+        let found = PythonInterpreter::find_all_with_source(target, false)?;
+        Ok(found
+            .into_iter()
+            .map(|(interpreter, _)| interpreter)
+            .collect())
+    }
+
+    /// Like [PythonInterpreter::find_all], but also returns where each interpreter was found.
+    ///
+    /// Binaries that aren't working interpreters are skipped with a warning. Of the interpreters
+    /// with the same implementation, version and abi, only the first one is kept, so a directory
+    /// early in PATH wins over pyenv and conda.
+    ///
+    /// With `refresh`, all interpreters are run again instead of using the cached metadata
+    pub fn find_all_with_source(
+        target: &Target,
+        refresh: bool,
+    ) -> Result<Vec<(PythonInterpreter, InterpreterSource)>, Error> {
         let executables = if target.is_windows() {
             find_all_windows(&target)?
         } else {
             find_all_unix()
         };
+        let mut cache = InterpreterCache::load(refresh);
+        let mut abis = HashSet::new();
         let mut available_versions = Vec::new();
-        for executable in executables {
-            if let Some(version) = PythonInterpreter::check_executable(&executable, &target)? {
-                available_versions.push(version);
+        for (executable, source) in executables {
+            let checked =
+                PythonInterpreter::check_executable_cached(&executable, &target, &mut cache);
+            let interpreter = match checked {
+                Ok(Some(interpreter)) => interpreter,
+                Ok(None) => continue,
+                Err(err) => {
+                    let causes: Vec<String> = err.iter_chain().map(ToString::to_string).collect();
+                    eprintln!("⚠ Warning: Skipping {}", causes.join(": "));
+                    continue;
+                }
+            };
+            let abi = (
+                interpreter.interpreter.clone(),
+                interpreter.major,
+                interpreter.minor,
+                interpreter.abiflags.clone(),
+                interpreter.abi_tag.clone(),
+            );
+            if abis.insert(abi) {
+                available_versions.push((interpreter, source));
             }
         }
+        cache.save();
+        available_versions.sort_by_key(|(interpreter, _)| {
+            (
+                interpreter.interpreter != Interpreter::CPython,
+                interpreter.major,
+                interpreter.minor,
+            )
+        });
 
         Ok(available_versions)
     }
 
+    /// Resolves the values of `-i`, which are either executables or selectors such as `3.7` or
+    /// `pypy3.6`. Selectors are looked up in the interpreters from [PythonInterpreter::find_all],
+    /// which runs only if there is a selector
+    pub fn find_selected(
+        values: &[PathBuf],
+        target: &Target,
+    ) -> Result<Vec<PythonInterpreter>, Error> {
+        let mut found: Option<Vec<PythonInterpreter>> = None;
+        let mut interpreters = Vec::new();
+        for value in values {
+            let selector = match value.to_str().and_then(InterpreterSelector::parse) {
+                Some(selector) => selector,
+                None => {
+                    let executables = [value.clone()];
+                    interpreters
+                        .extend(PythonInterpreter::check_executables(&executables, target)?);
+                    continue;
+                }
+            };
+            if found.is_none() {
+                found = Some(
+                    PythonInterpreter::find_all(target)
+                        .context("Finding python interpreters failed")?,
+                );
+            }
+            let found = found.as_ref().unwrap();
+            match found
+                .iter()
+                .find(|interpreter| selector.matches(interpreter))
+            {
+                Some(interpreter) => interpreters.push(interpreter.clone()),
+                None if found.is_empty() => bail!(
+                    "Couldn't find {} for `-i {}`, no python interpreters were found",
+                    selector,
+                    value.display()
+                ),
+                None => {
+                    let found: Vec<String> = found.iter().map(ToString::to_string).collect();
+                    bail!(
+                        "Couldn't find {} for `-i {}`, the found interpreters are: {}",
+                        selector,
+                        value.display(),
+                        found.join(", ")
+                    )
+                }
+            }
+        }
+        Ok(interpreters)
+    }
+
     /// Checks that given list of executables are all valid python intepreters,
     /// determines the abiflags and versions of those interpreters and
     /// returns them as [PythonInterpreter]
@@ -547,14 +1099,15 @@ impl PythonInterpreter {
         executables: &[PathBuf],
         target: &Target,
     ) -> Result<Vec<PythonInterpreter>, Error> {
+        let mut cache = InterpreterCache::load(false);
         let mut available_versions = Vec::new();
         for executable in executables {
-            if let Some(version) = PythonInterpreter::check_executable(executable, &target)
-                .context(format!(
-                    "{} is not a valid python interpreter",
-                    executable.display()
-                ))?
-            {
+            let checked =
+                PythonInterpreter::check_executable_cached(executable, &target, &mut cache);
+            if let Some(version) = checked.context(format!(
+                "{} is not a valid python interpreter",
+                executable.display()
+            ))? {
                 available_versions.push(version);
             } else {
                 bail!(
@@ -563,6 +1116,7 @@ impl PythonInterpreter {
                 );
             }
         }
+        cache.save();
 
         Ok(available_versions)
     }
@@ -581,3 +1135,354 @@ impl fmt::Display for PythonInterpreter {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Builds the message that get_interpreter_metadata.py would print from one of the files in
+    /// `sysconfig/`
+    fn message_from_sysconfig(path: &str) -> IntepreterMetadataMessage {
+        let sysconfig = fs::read_to_string(path).unwrap();
+        metadata_from_sysconfig(&parse_sysconfig_dump(&sysconfig)).unwrap()
+    }
+
+    /// What check_executable does with the message of an interpreter
+    fn cpython_from_message(
+        message: &IntepreterMetadataMessage,
+        target: &Target,
+    ) -> Result<PythonInterpreter, Error> {
+        let executable = PathBuf::from(format!("python{}.{}", message.major, message.minor));
+        PythonInterpreter::from_metadata_message(message.clone(), &executable, target)
+    }
+
+    #[test]
+    fn test_linux_names() {
+        let target =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        for (path, tag, library_name) in &[
+            (
+                "sysconfig/cpython-linux-3.5.txt",
+                "cp35-cp35m-manylinux1_x86_64",
+                "steinlaus.cpython-35m-x86_64-linux-gnu.so",
+            ),
+            (
+                "sysconfig/cpython-linux-3.6.txt",
+                "cp36-cp36m-manylinux1_x86_64",
+                "steinlaus.cpython-36m-x86_64-linux-gnu.so",
+            ),
+            (
+                "sysconfig/cpython-linux-3.11.txt",
+                "cp311-cp311-manylinux1_x86_64",
+                "steinlaus.cpython-311-x86_64-linux-gnu.so",
+            ),
+        ] {
+            let message = message_from_sysconfig(path);
+            let interpreter = cpython_from_message(&message, &target).unwrap();
+            let manylinux = Manylinux::Checked("manylinux1".to_string());
+            assert_eq!(&interpreter.get_tag(&manylinux), tag);
+            assert_eq!(&interpreter.get_library_name("steinlaus"), library_name);
+            assert_eq!(
+                format!("steinlaus{}", message.ext_suffix.unwrap()),
+                interpreter.get_library_name("steinlaus")
+            );
+        }
+    }
+
+    #[test]
+    fn test_abiflags() {
+        let target =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        let manylinux = Manylinux::Checked("manylinux2010".to_string());
+
+        // 3.8 has the same variables as 3.11, just with another version
+        let mut message = message_from_sysconfig("sysconfig/cpython-linux-3.11.txt");
+        message.minor = 8;
+        message.abi_tag = Some("38".to_string());
+        let interpreter = cpython_from_message(&message, &target).unwrap();
+        assert_eq!(
+            interpreter.get_tag(&manylinux),
+            "cp38-cp38-manylinux2010_x86_64"
+        );
+        assert_eq!(
+            interpreter.get_library_name("steinlaus"),
+            "steinlaus.cpython-38-x86_64-linux-gnu.so"
+        );
+
+        message.abiflags = Some("d".to_string());
+        let interpreter = cpython_from_message(&message, &target).unwrap();
+        assert_eq!(
+            interpreter.get_tag(&manylinux),
+            "cp38-cp38d-manylinux2010_x86_64"
+        );
+        assert_eq!(
+            interpreter.get_library_name("steinlaus"),
+            "steinlaus.cpython-38d-x86_64-linux-gnu.so"
+        );
+
+        message.abiflags = Some("m".to_string());
+        assert!(cpython_from_message(&message, &target).is_err());
+
+        let mut message = message_from_sysconfig("sysconfig/cpython-linux-3.6.txt");
+        message.abiflags = Some("dm".to_string());
+        let interpreter = cpython_from_message(&message, &target).unwrap();
+        assert_eq!(
+            interpreter.get_tag(&manylinux),
+            "cp36-cp36dm-manylinux2010_x86_64"
+        );
+        assert_eq!(
+            interpreter.get_library_name("steinlaus"),
+            "steinlaus.cpython-36dm-x86_64-linux-gnu.so"
+        );
+
+        message.abiflags = Some("".to_string());
+        assert!(cpython_from_message(&message, &target).is_err());
+    }
+
+    #[test]
+    fn test_freebsd_cross() {
+        let message = message_from_sysconfig("sysconfig/cpython-freebsd-3.7.txt");
+        let target =
+            Target::from_target_triple(Some("x86_64-unknown-freebsd".to_string())).unwrap();
+        assert!(target.is_bsd() && target.is_unix());
+        let interpreter = cpython_from_message(&message, &target).unwrap();
+
+        assert_eq!(
+            interpreter.get_tag(&Manylinux::Off),
+            "cp37-cp37m-freebsd_12_0_amd64"
+        );
+        assert_eq!(
+            format!("steinlaus{}", interpreter.ext_suffix.clone().unwrap()),
+            interpreter.get_library_name("steinlaus")
+        );
+        assert_eq!(
+            interpreter.target.get_venv_python("venv"),
+            Path::new("venv").join("bin").join("python")
+        );
+
+        let linux =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        assert!(fun_with_abiflags(&message, &linux).is_err());
+    }
+
+    #[test]
+    fn test_is_python3_binary() {
+        for name in &["python3", "python3.7", "python3.10", "pypy3", "pypy3.6"] {
+            assert!(is_python3_binary(name), "{}", name);
+        }
+        for name in &[
+            "python",
+            "python2.7",
+            "python3-config",
+            "python3.7m",
+            "pypy",
+        ] {
+            assert!(!is_python3_binary(name), "{}", name);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_binaries() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let first = tempdir.path().join("first");
+        let second = tempdir.path().join("second");
+        fs::create_dir(&first).unwrap();
+        fs::create_dir(&second).unwrap();
+        let executable = |path: PathBuf| {
+            fs::write(&path, "").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        };
+        for name in &[
+            "python3.7",
+            "python3.10",
+            "pypy3",
+            "python2.7",
+            "python3-config",
+        ] {
+            executable(first.join(name));
+        }
+        fs::write(first.join("python3.6"), "").unwrap();
+        symlink(first.join("python3.7"), first.join("python3")).unwrap();
+        symlink(first.join("python3.7"), second.join("python3.7")).unwrap();
+        executable(second.join("python3.8"));
+
+        let dirs = vec![
+            (first.clone(), InterpreterSource::Path),
+            (tempdir.path().join("missing"), InterpreterSource::Path),
+            (second.clone(), InterpreterSource::Pyenv),
+        ];
+        assert_eq!(
+            find_binaries(&dirs),
+            vec![
+                (first.join("pypy3"), InterpreterSource::Path),
+                (first.join("python3"), InterpreterSource::Path),
+                (first.join("python3.10"), InterpreterSource::Path),
+                (second.join("python3.8"), InterpreterSource::Pyenv),
+            ]
+        );
+        assert_eq!(
+            bin_dirs_in(tempdir.path()),
+            vec![first.join("bin"), second.join("bin")]
+        );
+    }
+
+    #[test]
+    fn test_sysconfig_dump() {
+        let linux =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        let path = Path::new("sysconfig/cpython-linux-3.6.txt");
+        let interpreter = PythonInterpreter::from_sysconfig_file(path, &linux).unwrap();
+        assert_eq!(interpreter.executable, path);
+        assert_eq!(interpreter.micro, Some(5));
+        assert_eq!(
+            interpreter.get_tag(&Manylinux::Off),
+            "cp36-cp36m-linux_x86_64"
+        );
+        assert_eq!(
+            interpreter.cross_compile,
+            Some(CrossCompileInfo {
+                lib_dir: PathBuf::from("/usr/lib"),
+                include_dir: Some(PathBuf::from("/usr/include/python3.6m")),
+            })
+        );
+
+        let path = Path::new("sysconfig/pypy-linux-3.6-7.0.txt");
+        let interpreter = PythonInterpreter::from_sysconfig_file(path, &linux).unwrap();
+        assert_eq!(interpreter.interpreter, Interpreter::PyPy);
+        assert_eq!(
+            interpreter.get_library_name("steinlaus"),
+            "steinlaus.pypy3-70-x86_64-linux-gnu.so"
+        );
+
+        let windows =
+            Target::from_target_triple(Some("x86_64-pc-windows-msvc".to_string())).unwrap();
+        let path = Path::new("sysconfig/cpython-win-3.7.txt");
+        let interpreter = PythonInterpreter::from_sysconfig_file(path, &windows).unwrap();
+        assert_eq!(interpreter.get_tag(&Manylinux::Off), "cp37-none-win_amd64");
+        assert!(PythonInterpreter::from_sysconfig_file(path, &linux).is_err());
+
+        let aarch64 =
+            Target::from_target_triple(Some("aarch64-unknown-linux-gnu".to_string())).unwrap();
+        let path = Path::new("sysconfig/cpython-linux-3.6.txt");
+        assert!(PythonInterpreter::from_sysconfig_file(path, &aarch64).is_err());
+    }
+
+    #[test]
+    fn test_sysconfigdata() {
+        let path = Path::new("sysconfig/_sysconfigdata__linux_x86_64-linux-gnu.py");
+        let variables = parse_sysconfigdata(&fs::read_to_string(path).unwrap());
+        assert_eq!(variables["ABIFLAGS"], "");
+        assert_eq!(variables["Py_DEBUG"], "0");
+        assert_eq!(
+            variables["BLDSHARED"],
+            "gcc -shared -L/usr/local/lib -Wl,-rpath,/usr/local/lib \
+             -L/usr/local/lib -Wl,-rpath,/usr/local/lib"
+        );
+
+        let linux =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        let interpreter = PythonInterpreter::from_sysconfig_file(path, &linux).unwrap();
+        assert_eq!(interpreter.micro, None);
+        assert_eq!(
+            interpreter.get_tag(&Manylinux::Off),
+            "cp38-cp38-linux_x86_64"
+        );
+        assert_eq!(
+            interpreter.get_library_name("steinlaus"),
+            "steinlaus.cpython-38-x86_64-linux-gnu.so"
+        );
+        assert_eq!(
+            interpreter.cross_compile,
+            Some(CrossCompileInfo {
+                lib_dir: PathBuf::from("sysconfig"),
+                include_dir: Some(PathBuf::from("/usr/local/include/python3.8")),
+            })
+        );
+
+        // There's no sysconfig.get_platform() in _sysconfigdata_*.py
+        let variables = parse_sysconfigdata(
+            "build_time_vars = {'ABIFLAGS': 'm', 'HOST_GNU_TYPE': 'amd64-portbld-freebsd12.0', \
+             'MACHDEP': 'freebsd12', 'SOABI': 'cpython-37m', 'VERSION': '3.7'}",
+        );
+        let message = metadata_from_sysconfig(&variables).unwrap();
+        assert_eq!(message.sysconfig_platform, "freebsd-12.0");
+        assert_eq!(message.abi_tag, Some("37m".to_string()));
+    }
+
+    #[test]
+    fn test_interpreter_selector() {
+        let linux =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        let cpython = Path::new("sysconfig/cpython-linux-3.6.txt");
+        let cpython = PythonInterpreter::from_sysconfig_file(cpython, &linux).unwrap();
+        let pypy = Path::new("sysconfig/pypy-linux-3.6-7.0.txt");
+        let pypy = PythonInterpreter::from_sysconfig_file(pypy, &linux).unwrap();
+
+        let selector = InterpreterSelector::parse("3.6").unwrap();
+        assert_eq!(selector.to_string(), "CPython 3.6");
+        assert!(selector.matches(&cpython));
+        assert!(!selector.matches(&pypy));
+        assert!(InterpreterSelector::parse("cpython3.6")
+            .unwrap()
+            .matches(&cpython));
+        let selector = InterpreterSelector::parse("pypy3.6").unwrap();
+        assert!(selector.matches(&pypy));
+        assert!(!selector.matches(&cpython));
+        assert!(!InterpreterSelector::parse("3.7").unwrap().matches(&cpython));
+        assert_eq!(
+            InterpreterSelector::parse("3.10"),
+            Some(InterpreterSelector {
+                interpreter: Interpreter::CPython,
+                major: 3,
+                minor: 10
+            })
+        );
+
+        for executable in &[
+            "python3.6",
+            "pypy3",
+            "/usr/bin/python3.6",
+            "3",
+            "3.6m",
+            "jython3.6",
+        ] {
+            assert_eq!(InterpreterSelector::parse(executable), None);
+        }
+    }
+
+    #[test]
+    fn test_pypy_tags() {
+        let linux =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        let manylinux = Manylinux::Checked("manylinux2010".to_string());
+        let path = Path::new("sysconfig/pypy-linux-3.6-7.0.txt");
+        let mut interpreter = PythonInterpreter::from_sysconfig_file(path, &linux).unwrap();
+        assert_eq!(
+            interpreter.get_tag(&manylinux),
+            "pp36-pypy3_70-manylinux2010_x86_64"
+        );
+        assert_eq!(
+            interpreter.get_tag(&Manylinux::Off),
+            "pp36-pypy3_70-linux_x86_64"
+        );
+
+        // Since 7.3, the soabi contains the python version, e.g. pypy3.7-v7.3.0
+        interpreter.minor = 7;
+        interpreter.ext_suffix = Some(".pypy37-pp73-x86_64-linux-gnu.so".to_string());
+        interpreter.abi_tag = Some("pp73".to_string());
+        assert_eq!(
+            interpreter.get_tag(&manylinux),
+            "pp37-pypy37_pp73-manylinux2010_x86_64"
+        );
+        assert_eq!(
+            interpreter.get_library_name("steinlaus"),
+            "steinlaus.pypy37-pp73-x86_64-linux-gnu.so"
+        );
+
+        interpreter.ext_suffix = Some(".pyd".to_string());
+        assert_eq!(interpreter.get_pypy_abi_tag(), "pypy37_pp73");
+    }
+}
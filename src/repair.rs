@@ -0,0 +1,476 @@
+//! A reimplementation of `auditwheel repair`: Libraries that aren't whitelisted by the manylinux
+//! policy are copied into the wheel under a hash-mangled soname and the native library is
+//! patched to load them from there.
+//!
+//! Instead of depending on patchelf, the elf files are edited directly: The extended dynamic
+//! string table, the new dynamic section and the program header table are appended to the file
+//! in a new loadable segment, which is the same strategy patchelf uses.
+
+use crate::auditwheel::{auditwheel_rs, find_external_libs, get_dynstr, get_rpaths};
+use crate::module_writer::BundledLibraries;
+use crate::Policy;
+use crate::Target;
+use failure::{bail, format_err, Error, ResultExt};
+use goblin::container::{Container, Ctx};
+use goblin::elf::dynamic::{Dyn, DT_NEEDED, DT_NULL, DT_RPATH, DT_RUNPATH, DT_SONAME};
+use goblin::elf::dynamic::{DT_STRSZ, DT_STRTAB};
+use goblin::elf::program_header::{ProgramHeader, PF_R, PF_W, PT_DYNAMIC, PT_LOAD, PT_PHDR};
+use goblin::elf::section_header::{SectionHeader, SHT_DYNAMIC};
+use goblin::elf::Elf;
+use scroll::{Endian, Pread, Pwrite};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Returns the directories the dynamic loader searches by default. Debian and its derivatives
+/// put the libraries into a directory named after the multiarch tuple of the target, e.g.
+/// `/usr/lib/aarch64-linux-gnu`
+fn default_library_paths(target: &Target) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = ["/lib64", "/usr/lib64", "/lib", "/usr/lib"]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+    if let Some(multiarch) = target.get_shared_platform_tag() {
+        paths.push(Path::new("/lib").join(multiarch));
+        paths.push(Path::new("/usr/lib").join(multiarch));
+    }
+    paths.push(PathBuf::from("/usr/local/lib"));
+    paths
+}
+
+/// A native library whose external dependencies were bundled
+pub struct RepairedArtifact {
+    /// The patched copy of the native library, which loads the bundled libraries
+    pub artifact: PathBuf,
+    /// The bundled libraries as pairs of mangled soname and patched content
+    pub libraries: BundledLibraries,
+}
+
+/// Returns the directories in which the dynamic loader would look for the dependencies of the
+/// library at `path`, in the order the loader uses: the rpaths of the library, LD_LIBRARY_PATH,
+/// the directories from /etc/ld.so.conf.d and the default directories
+fn library_search_paths(path: &Path, elf: &Elf, target: &Target) -> Result<Vec<PathBuf>, Error> {
+    let origin = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut search_paths: Vec<PathBuf> = get_rpaths(elf)?
+        .iter()
+        .map(|rpath| PathBuf::from(rpath.replace("$ORIGIN", &origin.to_string_lossy())))
+        .collect();
+
+    if let Some(ld_library_path) = env::var_os("LD_LIBRARY_PATH") {
+        search_paths.extend(env::split_paths(&ld_library_path));
+    }
+
+    if let Ok(entries) = fs::read_dir("/etc/ld.so.conf.d") {
+        for entry in entries.filter_map(Result::ok) {
+            if let Ok(contents) = fs::read_to_string(entry.path()) {
+                search_paths.extend(
+                    contents
+                        .lines()
+                        .map(str::trim)
+                        .filter(|line| line.starts_with('/'))
+                        .map(PathBuf::from),
+                );
+            }
+        }
+    }
+
+    search_paths.extend(default_library_paths(target));
+    Ok(search_paths)
+}
+
+/// Adds a hash of the library's content to its name, e.g. `libfoo.so.1` becomes
+/// `libfoo-1a2b3c4d.so.1`, so that the bundled copy can't clash with other versions of the
+/// same library
+fn mangle_soname(soname: &str, content: &[u8]) -> String {
+    let hash: String = Sha256::digest(content)
+        .iter()
+        .take(4)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    match soname.find('.') {
+        Some(index) => format!("{}-{}{}", &soname[..index], hash, &soname[index..]),
+        None => format!("{}-{}", soname, hash),
+    }
+}
+
+/// Rounds `value` up to the next multiple of `alignment`
+fn align_up(value: u64, alignment: u64) -> u64 {
+    (value + alignment - 1) & !(alignment - 1)
+}
+
+/// Appends a null terminated string to the string table and returns its offset
+fn add_string(strtab: &mut Vec<u8>, value: &str) -> u64 {
+    let offset = strtab.len() as u64;
+    strtab.extend_from_slice(value.as_bytes());
+    strtab.push(0);
+    offset
+}
+
+/// Rewrites the dynamic section of an elf file: Optionally sets the soname, renames the NEEDED
+/// libraries according to `replace_needed` and sets the RUNPATH, dropping any existing RPATH.
+///
+/// The old dynamic string table is kept as prefix of the new one, so all other references into it
+/// (e.g. from the symbol table) stay valid.
+pub fn patch_elf(
+    buffer: &[u8],
+    soname: Option<&str>,
+    replace_needed: &HashMap<String, String>,
+    runpath: &str,
+) -> Result<Vec<u8>, Error> {
+    let elf = Elf::parse(buffer)?;
+    let container = if elf.is_64 {
+        Container::Big
+    } else {
+        Container::Little
+    };
+    let endian = if elf.little_endian {
+        Endian::Little
+    } else {
+        Endian::Big
+    };
+    let ctx = Ctx::new(container, endian);
+    let dynamic = match elf.dynamic {
+        Some(ref dynamic) => dynamic,
+        None => bail!("The library doesn't have a dynamic section"),
+    };
+
+    let mut strtab = buffer
+        .get(dynamic.info.strtab..dynamic.info.strtab + dynamic.info.strsz)
+        .ok_or_else(|| format_err!("The dynamic string table is out of bounds"))?
+        .to_vec();
+
+    let mut new_names = HashMap::new();
+    let mut dyns = Vec::new();
+    for dyn_ in &dynamic.dyns {
+        match dyn_.d_tag {
+            DT_NULL => break,
+            DT_NEEDED => {
+                let name = get_dynstr(&elf, dyn_.d_val as usize)?;
+                match replace_needed.get(name) {
+                    Some(new_name) => {
+                        let offset = add_string(&mut strtab, new_name);
+                        new_names.insert(name.to_string(), offset);
+                        dyns.push(Dyn {
+                            d_tag: DT_NEEDED,
+                            d_val: offset,
+                        });
+                    }
+                    None => dyns.push(dyn_.clone()),
+                }
+            }
+            DT_SONAME if soname.is_some() => {}
+            DT_RPATH | DT_RUNPATH => {}
+            _ => dyns.push(dyn_.clone()),
+        }
+    }
+    if let Some(soname) = soname {
+        dyns.push(Dyn {
+            d_tag: DT_SONAME,
+            d_val: add_string(&mut strtab, soname),
+        });
+    }
+    dyns.push(Dyn {
+        d_tag: DT_RUNPATH,
+        d_val: add_string(&mut strtab, runpath),
+    });
+    dyns.push(Dyn {
+        d_tag: DT_NULL,
+        d_val: 0,
+    });
+
+    // The new segment contains the program header table, the string table and the dynamic
+    // section, in that order. It's placed after the end of the file and after the highest
+    // virtual address in use.
+    let mut program_headers = elf.program_headers.clone();
+    let loads = || program_headers.iter().filter(|ph| ph.p_type == PT_LOAD);
+    let alignment = loads().map(|ph| ph.p_align).max().unwrap_or(0).max(0x1000);
+    let vm_end = loads().map(|ph| ph.p_vaddr + ph.p_memsz).max().unwrap_or(0);
+    let segment_offset = align_up(buffer.len() as u64, alignment);
+    let segment_vaddr = align_up(vm_end, alignment);
+
+    let phdrs_size = ((program_headers.len() + 1) * ProgramHeader::size(ctx)) as u64;
+    let strtab_start = phdrs_size;
+    let dynamic_start = align_up(strtab_start + strtab.len() as u64, 8);
+    let dynamic_size = (dyns.len() * Dyn::size(container)) as u64;
+    let segment_size = dynamic_start + dynamic_size;
+
+    for dyn_ in &mut dyns {
+        match dyn_.d_tag {
+            DT_STRTAB => dyn_.d_val = segment_vaddr + strtab_start,
+            DT_STRSZ => dyn_.d_val = strtab.len() as u64,
+            _ => {}
+        }
+    }
+
+    for ph in &mut program_headers {
+        match ph.p_type {
+            PT_DYNAMIC => {
+                ph.p_offset = segment_offset + dynamic_start;
+                ph.p_vaddr = segment_vaddr + dynamic_start;
+                ph.p_paddr = segment_vaddr + dynamic_start;
+                ph.p_filesz = dynamic_size;
+                ph.p_memsz = dynamic_size;
+            }
+            PT_PHDR => {
+                ph.p_offset = segment_offset;
+                ph.p_vaddr = segment_vaddr;
+                ph.p_paddr = segment_vaddr;
+                ph.p_filesz = phdrs_size;
+                ph.p_memsz = phdrs_size;
+            }
+            _ => {}
+        }
+    }
+    program_headers.push(ProgramHeader {
+        p_type: PT_LOAD,
+        p_flags: PF_R | PF_W,
+        p_offset: segment_offset,
+        p_vaddr: segment_vaddr,
+        p_paddr: segment_vaddr,
+        p_filesz: segment_size,
+        p_memsz: segment_size,
+        p_align: alignment,
+    });
+
+    let mut output = buffer.to_vec();
+    output.resize((segment_offset + segment_size) as usize, 0);
+
+    let mut offset = segment_offset as usize;
+    for ph in program_headers.iter().cloned() {
+        offset += output.pwrite_with(ph, offset, ctx)?;
+    }
+    let start = (segment_offset + strtab_start) as usize;
+    output[start..start + strtab.len()].copy_from_slice(&strtab);
+    let mut offset = (segment_offset + dynamic_start) as usize;
+    for dyn_ in dyns {
+        offset += output.pwrite_with(dyn_, offset, ctx)?;
+    }
+
+    // The version requirements reference the libraries by name, so they need to be renamed, too
+    let mut verneed_offset = dynamic.info.verneed as usize;
+    for _ in 0..dynamic.info.verneednum {
+        if verneed_offset == 0 {
+            break;
+        }
+        let vn_file: u32 = output.pread_with(verneed_offset + 4, endian)?;
+        if let Some(new_offset) = new_names.get(get_dynstr(&elf, vn_file as usize)?) {
+            output.pwrite_with(*new_offset as u32, verneed_offset + 4, endian)?;
+        }
+        let vn_next: u32 = output.pread_with(verneed_offset + 12, endian)?;
+        if vn_next == 0 {
+            break;
+        }
+        verneed_offset += vn_next as usize;
+    }
+
+    // Not required for loading the library, but tools such as readelf read the section headers
+    let dynamic_section = elf
+        .section_headers
+        .iter()
+        .position(|sh| sh.sh_type == SHT_DYNAMIC);
+    let strtab_section = dynamic_section.map(|index| elf.section_headers[index].sh_link as usize);
+    for (index, sh) in elf.section_headers.iter().enumerate() {
+        let mut sh: SectionHeader = sh.clone();
+        if Some(index) == dynamic_section {
+            sh.sh_offset = segment_offset + dynamic_start;
+            sh.sh_addr = segment_vaddr + dynamic_start;
+            sh.sh_size = dynamic_size;
+        } else if Some(index) == strtab_section {
+            sh.sh_offset = segment_offset + strtab_start;
+            sh.sh_addr = segment_vaddr + strtab_start;
+            sh.sh_size = strtab.len() as u64;
+        } else {
+            continue;
+        }
+        let offset = elf.header.e_shoff as usize + index * elf.header.e_shentsize as usize;
+        output.pwrite_with(sh, offset, ctx)?;
+    }
+
+    let mut header = elf.header;
+    header.e_phoff = segment_offset;
+    header.e_phnum = program_headers.len() as u16;
+    output.pwrite_with(header, 0, endian)?;
+
+    Ok(output)
+}
+
+/// Finds the libraries the native library at `artifact` needs but which the policy doesn't
+/// whitelist, including the dependencies of those libraries. The libraries are patched
+/// to use a hash-mangled soname and to find each other through `$ORIGIN`, while the native library
+/// gets `runpath` as RUNPATH, which must point to the directory the libraries will be put in.
+///
+/// The patched native library is written to a `repaired` directory next to `artifact`, so cargo's
+/// output stays untouched. Returns `None` if there are no libraries to bundle.
+pub fn repair(
+    artifact: &Path,
+    target: &Target,
+    policy: &Policy,
+    runpath: &str,
+) -> Result<Option<RepairedArtifact>, Error> {
+    if !target.is_linux() {
+        return Ok(None);
+    }
+
+    // Maps the original soname to the location of the library and the mangled soname
+    let mut libraries: BTreeMap<String, (PathBuf, String)> = BTreeMap::new();
+    let mut queue = vec![artifact.to_path_buf()];
+    while let Some(path) = queue.pop() {
+        let buffer = fs::read(&path).context(format!("Failed to read {}", path.display()))?;
+        let elf = Elf::parse(&buffer)?;
+        for external_lib in find_external_libs(&elf, policy) {
+            if libraries.contains_key(&external_lib) {
+                continue;
+            }
+            let source = library_search_paths(&path, &elf, target)?
+                .into_iter()
+                .map(|dir| dir.join(&external_lib))
+                .find(|candidate| candidate.is_file())
+                .ok_or_else(|| {
+                    format_err!(
+                        "Couldn't find {}, which is required by {}",
+                        external_lib,
+                        path.display()
+                    )
+                })?;
+            // The bundled libraries must themselves only use symbol versions the policy allows
+            auditwheel_rs(&source, target, policy, true, 0)
+                .context(format!("Can't bundle {}", source.display()))?;
+            let content = fs::read(&source)?;
+            let mangled = mangle_soname(&external_lib, &content);
+            libraries.insert(external_lib, (source.clone(), mangled));
+            queue.push(source);
+        }
+    }
+
+    if libraries.is_empty() {
+        return Ok(None);
+    }
+
+    let replace_needed: HashMap<String, String> = libraries
+        .iter()
+        .map(|(soname, (_, mangled))| (soname.clone(), mangled.clone()))
+        .collect();
+
+    let mut patched_libraries = Vec::new();
+    for (soname, (source, mangled)) in &libraries {
+        println!("📎 Bundling {} as {}", soname, mangled);
+        let patched = patch_elf(
+            &fs::read(source)?,
+            Some(mangled),
+            &replace_needed,
+            "$ORIGIN",
+        )
+        .context(format!("Failed to patch {}", source.display()))?;
+        patched_libraries.push((mangled.clone(), patched));
+    }
+
+    let repaired_dir = artifact
+        .parent()
+        .expect("Expected the native library to be in a directory")
+        .join("repaired");
+    fs::create_dir_all(&repaired_dir)?;
+    let patched_artifact = repaired_dir.join(
+        artifact
+            .file_name()
+            .expect("Expected the native library to have a filename"),
+    );
+    let patched = patch_elf(&fs::read(artifact)?, None, &replace_needed, runpath)
+        .context(format!("Failed to patch {}", artifact.display()))?;
+    fs::write(&patched_artifact, patched)?;
+
+    Ok(Some(RepairedArtifact {
+        artifact: patched_artifact,
+        libraries: patched_libraries,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    /// libuser needs libdep and finds it through a RUNPATH of `$ORIGIN`. The fixtures were built
+    /// with `gcc -shared -fPIC -O0 -s -Wl,-soname,libdep.so.1 -o libdep.so.1 dep.c` and
+    /// `gcc -shared -fPIC -O0 -s -o libuser.so.1 user.c -L. -l:libdep.so.1 -Wl,-rpath,'$ORIGIN'
+    /// -Wl,--enable-new-dtags`
+    #[test]
+    fn test_patch_elf() {
+        let buffer = fs::read("test-data/libuser.so.1").unwrap();
+        let mut replace_needed = HashMap::new();
+        replace_needed.insert(
+            "libdep.so.1".to_string(),
+            "libdep-01234567.so.1".to_string(),
+        );
+        let patched = patch_elf(
+            &buffer,
+            Some("libuser-89abcdef.so.1"),
+            &replace_needed,
+            "$ORIGIN/user.libs",
+        )
+        .unwrap();
+
+        let elf = Elf::parse(&patched).unwrap();
+        assert!(elf.libraries.contains(&"libdep-01234567.so.1"));
+        assert!(!elf.libraries.contains(&"libdep.so.1"));
+        assert_eq!(elf.soname, Some("libuser-89abcdef.so.1"));
+        assert_eq!(
+            get_rpaths(&elf).unwrap(),
+            vec!["$ORIGIN/user.libs".to_string()]
+        );
+        // The symbols must still resolve to the same names
+        let names: Vec<&str> = elf
+            .dynsyms
+            .iter()
+            .map(|sym| &elf.dynstrtab[sym.st_name])
+            .collect();
+        assert!(names.contains(&"call_answer"));
+        assert!(names.contains(&"answer"));
+    }
+
+    #[test]
+    fn test_default_library_paths() {
+        let aarch64 =
+            Target::from_target_triple(Some("aarch64-unknown-linux-gnu".to_string())).unwrap();
+        let paths = default_library_paths(&aarch64);
+        assert!(paths.contains(&PathBuf::from("/usr/lib/aarch64-linux-gnu")));
+        assert!(!paths.contains(&PathBuf::from("/usr/lib/x86_64-linux-gnu")));
+    }
+
+    #[test]
+    fn test_repair() {
+        let tempdir = tempfile::tempdir().unwrap();
+        for library in &["libdep.so.1", "libuser.so.1"] {
+            fs::copy(
+                Path::new("test-data").join(library),
+                tempdir.path().join(library),
+            )
+            .unwrap();
+        }
+        let target =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        let artifact = tempdir.path().join("libuser.so.1");
+
+        let policies = Policy::embedded();
+        let manylinux1 = Policy::find(&policies, "manylinux1").unwrap();
+
+        let compliant = Path::new("test-data/libcompliant.so.1");
+        assert!(repair(compliant, &target, manylinux1, "$ORIGIN")
+            .unwrap()
+            .is_none());
+
+        let repaired = repair(&artifact, &target, manylinux1, "$ORIGIN/user.libs")
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(repaired.libraries.len(), 1);
+        let (soname, content) = &repaired.libraries[0];
+        assert!(soname.starts_with("libdep-") && soname.ends_with(".so.1"));
+        assert_eq!(Elf::parse(content).unwrap().soname, Some(soname.as_str()));
+
+        let buffer = fs::read(&repaired.artifact).unwrap();
+        let elf = Elf::parse(&buffer).unwrap();
+        assert_eq!(elf.libraries, vec![soname.as_str()]);
+    }
+}
@@ -1,10 +1,11 @@
 use failure::{bail, format_err, Error};
 use platforms;
-use platforms::target::Arch;
 use serde::{Deserialize, Serialize};
 use std::env;
 use std::path::Path;
 use std::path::PathBuf;
+use std::process::Command;
+use std::str;
 use std::str::FromStr;
 use target_info;
 
@@ -14,19 +15,57 @@ enum OS {
     Linux,
     Windows,
     Macos,
+    FreeBsd,
+    NetBsd,
+    OpenBsd,
+}
+
+/// Returns the name of the operating system as in rust's `target_os`, e.g. `freebsd`
+fn os_name(os: &OS) -> &'static str {
+    match os {
+        OS::Linux => "linux",
+        OS::Windows => "windows",
+        OS::Macos => "macos",
+        OS::FreeBsd => "freebsd",
+        OS::NetBsd => "netbsd",
+        OS::OpenBsd => "openbsd",
+    }
+}
+
+/// Returns the release of the running operating system without the suffix, e.g. `12.0` for
+/// FreeBSD's `12.0-RELEASE`
+fn get_host_release() -> Option<String> {
+    let output = Command::new("uname").arg("-r").output().ok()?;
+    let release = str::from_utf8(&output.stdout).ok()?.trim();
+    release.split('-').next().map(ToString::to_string)
+}
+
+/// All supported CPU architectures
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Arch {
+    Aarch64,
+    Armv7L,
+    Powerpc64Le,
+    S390X,
+    X86,
+    X86_64,
 }
 
 /// Decides how to handle manylinux compliance
+///
+/// The policies are referenced by name, e.g. `manylinux2010`, so that new policies only need an
+/// entry in the policy file
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub enum Manylinux {
-    /// Use the manylinux1 tag and check for compliance
-    Manylinux1,
-    /// Use the manylinux1 tag but don't check for compliance
-    Manylinux1Unchecked,
-    /// Use manylinux2010 tag and check for compliance
-    Manylinux2010,
-    /// Use the manylinux2010 tag but don't check for compliance
-    Manylinux2010Unchecked,
+    /// Use the tag of the policy and check for compliance
+    Checked(String),
+    /// Use the tag of the policy but don't check for compliance
+    Unchecked(String),
+    /// Use the tag of the policy and bundle the libraries that aren't whitelisted into the wheel
+    Repair(String),
+    /// Check compliance with all policies and use the tag of the oldest one the library complies
+    /// with. Resolved to one of the other variants once the library was built
+    Auto,
     /// Use the native linux tag
     Off,
 }
@@ -34,23 +73,84 @@ pub enum Manylinux {
 impl FromStr for Manylinux {
     type Err = &'static str;
 
+    /// Parses values such as `2010`, `2010-unchecked`, `1-repair`, `musllinux_1_1`, `auto` or
+    /// `off`. Full policy names such as `manylinux_2_24` are used as they are, whether the policy
+    /// exists is only checked once the policies are loaded
     fn from_str(value: &str) -> Result<Self, Self::Err> {
         match value {
-            "1" => Ok(Manylinux::Manylinux1),
-            "1-unchecked" => Ok(Manylinux::Manylinux1Unchecked),
-            "2010" => Ok(Manylinux::Manylinux2010),
-            "2010-unchecked" => Ok(Manylinux::Manylinux2010Unchecked),
-            "off" => Ok(Manylinux::Off),
+            "auto" => return Ok(Manylinux::Auto),
+            "off" => return Ok(Manylinux::Off),
+            _ => {}
+        }
+        let (version, mode) = match value.find('-') {
+            Some(index) => (&value[..index], &value[index + 1..]),
+            None => (value, ""),
+        };
+        if version.is_empty() {
+            return Err("Invalid value for the manylinux option");
+        }
+        let policy = if version.starts_with("manylinux") || version.starts_with("musllinux") {
+            version.to_string()
+        } else {
+            format!("manylinux{}", version)
+        };
+        match mode {
+            "" => Ok(Manylinux::Checked(policy)),
+            "unchecked" => Ok(Manylinux::Unchecked(policy)),
+            "repair" => Ok(Manylinux::Repair(policy)),
             _ => Err("Invalid value for the manylinux option"),
         }
     }
 }
 
+impl Manylinux {
+    /// Returns the name of the policy whose tag is used, or `None` for the native linux tag
+    pub fn policy_name(&self) -> Option<&str> {
+        match self {
+            Manylinux::Checked(policy)
+            | Manylinux::Unchecked(policy)
+            | Manylinux::Repair(policy) => Some(policy),
+            Manylinux::Auto | Manylinux::Off => None,
+        }
+    }
+
+    /// Returns the name of the policy to check compliance against, if any
+    pub fn checked_policy_name(&self) -> Option<&str> {
+        match self {
+            Manylinux::Checked(policy) | Manylinux::Repair(policy) => Some(policy),
+            Manylinux::Unchecked(_) | Manylinux::Auto | Manylinux::Off => None,
+        }
+    }
+
+    /// Returns true if the libraries that aren't whitelisted should be bundled into the wheel
+    pub fn is_repair(&self) -> bool {
+        matches!(self, Manylinux::Repair(_))
+    }
+
+    /// Returns the same mode with another policy, e.g. `2010-repair` becomes
+    /// `musllinux_1_1-repair`
+    pub fn with_policy(&self, policy: &str) -> Manylinux {
+        match self {
+            Manylinux::Checked(_) => Manylinux::Checked(policy.to_string()),
+            Manylinux::Unchecked(_) => Manylinux::Unchecked(policy.to_string()),
+            Manylinux::Repair(_) => Manylinux::Repair(policy.to_string()),
+            Manylinux::Auto => Manylinux::Auto,
+            Manylinux::Off => Manylinux::Off,
+        }
+    }
+}
+
 /// The part of the current platform that is relevant when building wheels and is supported
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Target {
     os: OS,
-    is_64_bit: bool,
+    arch: Arch,
+    is_musl: bool,
+    /// The release of a bsd, e.g. `12.0`, or the minimum mac os version, e.g. `10.9`, which are
+    /// part of the platform tag
+    os_release: Option<String>,
+    /// Whether to build a fat binary for x86_64 and arm64 on mac os
+    is_universal2: bool,
 }
 
 impl Target {
@@ -60,16 +160,45 @@ impl Target {
             "linux" => OS::Linux,
             "windows" => OS::Windows,
             "macos" => OS::Macos,
+            "freebsd" => OS::FreeBsd,
+            "netbsd" => OS::NetBsd,
+            "openbsd" => OS::OpenBsd,
             unsupported => panic!("The platform {} is not supported", unsupported),
         };
 
-        let is_64_bit = match target_info::Target::pointer_width() {
-            "64" => true,
-            "32" => false,
-            unsupported => panic!("The pointer width {} is not supported ಠ_ಠ", unsupported),
+        // target_info doesn't know about powerpc64 and s390x
+        let arch = if cfg!(target_arch = "x86_64") {
+            Arch::X86_64
+        } else if cfg!(target_arch = "x86") {
+            Arch::X86
+        } else if cfg!(target_arch = "aarch64") {
+            Arch::Aarch64
+        } else if cfg!(target_arch = "arm") {
+            Arch::Armv7L
+        } else if cfg!(all(target_arch = "powerpc64", target_endian = "little")) {
+            Arch::Powerpc64Le
+        } else if cfg!(target_arch = "s390x") {
+            Arch::S390X
+        } else {
+            panic!(
+                "The architecture {} is not supported",
+                target_info::Target::arch()
+            )
+        };
+
+        let is_musl = target_info::Target::env() == "musl";
+        let os_release = match os {
+            OS::FreeBsd | OS::NetBsd | OS::OpenBsd => get_host_release(),
+            _ => None,
         };
 
-        Target { os, is_64_bit }
+        Target {
+            os,
+            arch,
+            is_musl,
+            os_release,
+            is_universal2: false,
+        }
     }
 
     /// Uses the given target triple or tries the guess the current target by using the one used
@@ -77,6 +206,21 @@ impl Target {
     ///
     /// Fails if the target triple isn't supported
     pub fn from_target_triple(target_triple: Option<String>) -> Result<Self, Error> {
+        // The platforms crate doesn't know apple silicon yet
+        let apple_silicon = match target_triple {
+            Some(ref target_triple) => target_triple == "aarch64-apple-darwin",
+            None => cfg!(all(target_os = "macos", target_arch = "aarch64")),
+        };
+        if apple_silicon {
+            return Ok(Target {
+                os: OS::Macos,
+                arch: Arch::Aarch64,
+                is_musl: false,
+                os_release: None,
+                is_universal2: false,
+            });
+        }
+
         let platform = if let Some(ref target_triple) = target_triple {
             platforms::find(target_triple)
                 .ok_or_else(|| format_err!("Unknown target triple {}", target_triple))?
@@ -89,28 +233,114 @@ impl Target {
             platforms::target::OS::Linux => OS::Linux,
             platforms::target::OS::Windows => OS::Windows,
             platforms::target::OS::MacOS => OS::Macos,
+            platforms::target::OS::FreeBSD => OS::FreeBsd,
+            platforms::target::OS::NetBSD => OS::NetBsd,
+            platforms::target::OS::OpenBSD => OS::OpenBsd,
             unsupported => bail!("The operating system {:?} is not supported", unsupported),
         };
 
-        let is_64_bit = match platform.target_arch {
-            Arch::X86_64 => true,
-            Arch::X86 => false,
-            unsupported => bail!("The architecture {:?} is not supported", unsupported),
+        // The platforms crate doesn't distinguish between the arm versions and the endianness
+        // of powerpc64, so we need to look at the triple itself
+        let arch = match platform.target_arch {
+            platforms::target::Arch::X86_64 => Arch::X86_64,
+            platforms::target::Arch::X86 => Arch::X86,
+            platforms::target::Arch::AARCH64 => Arch::Aarch64,
+            platforms::target::Arch::ARM if platform.target_triple.starts_with("armv7") => {
+                Arch::Armv7L
+            }
+            platforms::target::Arch::POWERPC64
+                if platform.target_triple.starts_with("powerpc64le") =>
+            {
+                Arch::Powerpc64Le
+            }
+            platforms::target::Arch::S390X => Arch::S390X,
+            _ => bail!(
+                "The architecture of {} is not supported",
+                platform.target_triple
+            ),
+        };
+
+        match (&os, arch) {
+            (OS::Linux, _) | (_, Arch::X86_64) | (OS::Macos, Arch::Aarch64) => {}
+            (OS::Windows, Arch::X86)
+            | (OS::FreeBsd, Arch::X86)
+            | (OS::NetBsd, Arch::X86)
+            | (OS::OpenBsd, Arch::X86) => {}
+            _ => bail!(
+                "The architecture of {} is not supported for this operating system",
+                platform.target_triple
+            ),
+        }
+
+        let is_musl = platform.target_env == Some(platforms::target::Env::Musl);
+
+        // The release can only be read when building on the bsd itself, otherwise it's taken from
+        // the sysconfig of the interpreter
+        let os_release = match os {
+            OS::FreeBsd | OS::NetBsd | OS::OpenBsd if os_name(&os) == target_info::Target::os() => {
+                get_host_release()
+            }
+            _ => None,
         };
 
-        Ok(Target { os, is_64_bit })
+        Ok(Target {
+            os,
+            arch,
+            is_musl,
+            os_release,
+            is_universal2: false,
+        })
+    }
+
+    /// Returns the same target with the release of the bsd taken from python's
+    /// `sysconfig.get_platform()`, e.g. `12.0` from `freebsd-12.0-RELEASE-amd64`
+    pub fn with_sysconfig_platform(&self, sysconfig_platform: &str) -> Target {
+        let mut target = self.clone();
+        if self.is_bsd() {
+            if let Some(release) = sysconfig_platform.split('-').nth(1) {
+                target.os_release = Some(release.to_string());
+            }
+        }
+        target
+    }
+
+    /// Returns the same target with the minimum mac os version, e.g. `10.9`, or the release of a
+    /// bsd, e.g. `12.0`
+    pub fn with_os_release(&self, os_release: &str) -> Target {
+        let mut target = self.clone();
+        target.os_release = Some(os_release.to_string());
+        target
+    }
+
+    /// Returns the same mac os target, but building a fat binary for x86_64 and arm64
+    pub fn with_universal2(&self) -> Result<Target, Error> {
+        if !self.is_macos() {
+            bail!("universal2 wheels can only be built for mac os");
+        }
+        let mut target = self.clone();
+        target.is_universal2 = true;
+        Ok(target)
+    }
+
+    /// Returns true if this is mac os with a fat binary for x86_64 and arm64
+    pub fn is_universal2(&self) -> bool {
+        self.is_universal2
+    }
+
+    /// Returns the release of a bsd, e.g. `12.0`, or the minimum mac os version, if it is known
+    pub fn os_release(&self) -> Option<&str> {
+        self.os_release.as_deref()
     }
 
     /// Returns whether the platform is 64 bit or 32 bit
     pub fn pointer_width(&self) -> usize {
-        if self.is_64_bit {
-            64
-        } else {
-            32
+        match self.arch {
+            Arch::Aarch64 | Arch::Powerpc64Le | Arch::S390X | Arch::X86_64 => 64,
+            Arch::Armv7L | Arch::X86 => 32,
         }
     }
 
-    /// Returns true if the current platform is linux or mac os
+    /// Returns true if the current platform is linux, mac os or a bsd
     pub fn is_unix(&self) -> bool {
         self.os != OS::Windows
     }
@@ -120,6 +350,16 @@ impl Target {
         self.os == OS::Linux
     }
 
+    /// Returns true if the current platform is linux with musl as libc instead of glibc
+    pub fn is_musl(&self) -> bool {
+        self.is_musl
+    }
+
+    /// Returns true if the current platform is freebsd, netbsd or openbsd
+    pub fn is_bsd(&self) -> bool {
+        matches!(self.os, OS::FreeBsd | OS::NetBsd | OS::OpenBsd)
+    }
+
     /// Returns true if the current platform is mac os
     pub fn is_macos(&self) -> bool {
         self.os == OS::Macos
@@ -130,26 +370,79 @@ impl Target {
         self.os == OS::Windows
     }
 
+    /// Returns the architecture as used in the linux platform tags and in the policy file, e.g.
+    /// `x86_64` or `i686`
+    pub fn get_linux_arch(&self) -> &'static str {
+        match self.arch {
+            Arch::Aarch64 => "aarch64",
+            Arch::Armv7L => "armv7l",
+            Arch::Powerpc64Le => "ppc64le",
+            Arch::S390X => "s390x",
+            Arch::X86 => "i686",
+            Arch::X86_64 => "x86_64",
+        }
+    }
+
     /// Returns the platform part of the tag for the wheel name for cffi wheels
-    pub fn get_platform_tag(&self, manylinux: &Manylinux) -> &'static str {
-        match (&self.os, self.is_64_bit, manylinux) {
-            (&OS::Linux, true, Manylinux::Off) => "linux_x86_64",
-            (&OS::Linux, false, Manylinux::Off) => "linux_i686",
-            (&OS::Linux, true, Manylinux::Manylinux1) => "manylinux1_x86_64",
-            (&OS::Linux, true, Manylinux::Manylinux1Unchecked) => "manylinux1_x86_64",
-            (&OS::Linux, true, Manylinux::Manylinux2010) => "manylinux2010_x86_64",
-            (&OS::Linux, true, Manylinux::Manylinux2010Unchecked) => "manylinux2010_x86_64",
-            (&OS::Linux, false, Manylinux::Manylinux1) => "manylinux1_i686",
-            (&OS::Linux, false, Manylinux::Manylinux1Unchecked) => "manylinux1_i686",
-            (&OS::Linux, false, Manylinux::Manylinux2010) => "manylinux2010_i686",
-            (&OS::Linux, false, Manylinux::Manylinux2010Unchecked) => "manylinux2010_i686",
-            (&OS::Windows, true, _) => "win_amd64",
-            (&OS::Windows, false, _) => "win32",
-            (&OS::Macos, true, _) => "macosx_10_7_x86_64",
-            (&OS::Macos, false, _) => panic!("32-bit wheels are not supported for mac os"),
+    pub fn get_platform_tag(&self, manylinux: &Manylinux) -> String {
+        match (&self.os, self.arch) {
+            (&OS::Linux, _) => format!(
+                "{}_{}",
+                manylinux.policy_name().unwrap_or("linux"),
+                self.get_linux_arch()
+            ),
+            (&OS::Windows, Arch::X86_64) => "win_amd64".to_string(),
+            (&OS::Windows, Arch::X86) => "win32".to_string(),
+            (&OS::Macos, arch) => {
+                let arch = if self.is_universal2 {
+                    "universal2"
+                } else {
+                    match arch {
+                        Arch::Aarch64 => "arm64",
+                        Arch::X86_64 => "x86_64",
+                        _ => panic!("{:?} is not supported for mac os", arch),
+                    }
+                };
+                // These are rust's default deployment targets, arm64 needs at least 11.0
+                let default_version = if arch == "x86_64" { "10.7" } else { "11.0" };
+                let version = self.os_release.as_deref().unwrap_or(default_version);
+                let mut parts = version.split('.');
+                let major = parts.next().unwrap_or("10");
+                // Since macOS 11, the minor version is what the major version used to be, so
+                // pip only accepts `macosx_11_0` and not e.g. `macosx_11_3`
+                let minor = match major.parse::<u32>() {
+                    Ok(major) if major >= 11 => "0",
+                    _ => parts.next().unwrap_or("0"),
+                };
+                format!("macosx_{}_{}_{}", major, minor, arch)
+            }
+            (&OS::FreeBsd, _) | (&OS::NetBsd, _) | (&OS::OpenBsd, _) => {
+                let os = os_name(&self.os);
+                let release = self.os_release.as_ref().unwrap_or_else(|| {
+                    panic!("The release of {} must be known for the platform tag", os)
+                });
+                let arch = if self.arch == Arch::X86_64 {
+                    "amd64"
+                } else {
+                    "i386"
+                };
+                format!("{}_{}_{}", os, release.replace('.', "_"), arch)
+            }
+            (os, arch) => panic!("{:?} is not supported for {:?}", arch, os),
         }
     }
 
+    /// Returns the tag for wheels with the stable abi (abi3) for all python versions starting with
+    /// the given one, e.g. `cp36-abi3-manylinux1_x86_64`
+    pub fn get_abi3_tag(&self, major: u8, minor: u8, manylinux: &Manylinux) -> String {
+        format!(
+            "cp{}{}-abi3-{}",
+            major,
+            minor,
+            self.get_platform_tag(manylinux)
+        )
+    }
+
     /// Returns the tags for the WHEEL file for cffi wheels
     pub fn get_py2_and_py3_tags(&self, manylinux: &Manylinux) -> Vec<String> {
         vec![
@@ -158,25 +451,37 @@ impl Target {
         ]
     }
 
-    /// Returns the platform for the tag in the shared libaries file name
-    pub fn get_shared_platform_tag(&self) -> &'static str {
-        match self.os {
-            OS::Linux => {
-                if self.is_64_bit {
-                    "x86_64-linux-gnu"
-                } else {
-                    "x86-linux-gnu"
-                }
-            }
+    /// Returns the platform for the tag in the shared libaries file name, which is the
+    /// multiarch triplet on linux, e.g. `aarch64-linux-gnu`. The bsds don't have one
+    pub fn get_shared_platform_tag(&self) -> Option<&'static str> {
+        let tag = match self.os {
+            OS::Linux if self.is_musl => match self.arch {
+                Arch::Aarch64 => "aarch64-linux-musl",
+                Arch::Armv7L => "arm-linux-musleabihf",
+                Arch::Powerpc64Le => "powerpc64le-linux-musl",
+                Arch::S390X => "s390x-linux-musl",
+                Arch::X86 => "i386-linux-musl",
+                Arch::X86_64 => "x86_64-linux-musl",
+            },
+            OS::Linux => match self.arch {
+                Arch::Aarch64 => "aarch64-linux-gnu",
+                Arch::Armv7L => "arm-linux-gnueabihf",
+                Arch::Powerpc64Le => "powerpc64le-linux-gnu",
+                Arch::S390X => "s390x-linux-gnu",
+                Arch::X86 => "i386-linux-gnu",
+                Arch::X86_64 => "x86_64-linux-gnu",
+            },
             OS::Macos => "darwin",
             OS::Windows => {
-                if self.is_64_bit {
+                if self.arch == Arch::X86_64 {
                     "win_amd64"
                 } else {
                     "win32"
                 }
             }
-        }
+            OS::FreeBsd | OS::NetBsd | OS::OpenBsd => return None,
+        };
+        Some(tag)
     }
 
     /// Returns the path to the python executable inside a venv
@@ -221,3 +526,194 @@ impl Target {
         (tag, tags)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_linux_tags() {
+        let manylinux = Manylinux::Checked("manylinux2014".to_string());
+        let cases = [
+            ("x86_64-unknown-linux-gnu", "x86_64", "x86_64-linux-gnu", 64),
+            ("i686-unknown-linux-gnu", "i686", "i386-linux-gnu", 32),
+            (
+                "aarch64-unknown-linux-gnu",
+                "aarch64",
+                "aarch64-linux-gnu",
+                64,
+            ),
+            (
+                "armv7-unknown-linux-gnueabihf",
+                "armv7l",
+                "arm-linux-gnueabihf",
+                32,
+            ),
+            (
+                "powerpc64le-unknown-linux-gnu",
+                "ppc64le",
+                "powerpc64le-linux-gnu",
+                64,
+            ),
+            ("s390x-unknown-linux-gnu", "s390x", "s390x-linux-gnu", 64),
+        ];
+        for (triple, arch, shared_tag, pointer_width) in &cases {
+            let target = Target::from_target_triple(Some(triple.to_string())).unwrap();
+            assert_eq!(target.get_linux_arch(), *arch);
+            assert_eq!(
+                target.get_platform_tag(&manylinux),
+                format!("manylinux2014_{}", arch)
+            );
+            assert_eq!(target.get_shared_platform_tag(), Some(*shared_tag));
+            assert_eq!(target.pointer_width(), *pointer_width);
+        }
+    }
+
+    #[test]
+    fn test_musl_tags() {
+        let cases = [
+            ("x86_64-unknown-linux-musl", "x86_64-linux-musl"),
+            ("i686-unknown-linux-musl", "i386-linux-musl"),
+            ("aarch64-unknown-linux-musl", "aarch64-linux-musl"),
+            ("armv7-unknown-linux-musleabihf", "arm-linux-musleabihf"),
+        ];
+        let manylinux = Manylinux::Checked("musllinux_1_1".to_string());
+        for (triple, shared_tag) in &cases {
+            let target = Target::from_target_triple(Some(triple.to_string())).unwrap();
+            assert!(target.is_musl());
+            assert_eq!(
+                target.get_platform_tag(&manylinux),
+                format!("musllinux_1_1_{}", target.get_linux_arch())
+            );
+            assert_eq!(target.get_shared_platform_tag(), Some(*shared_tag));
+        }
+        let glibc = Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string()));
+        assert!(!glibc.unwrap().is_musl());
+    }
+
+    #[test]
+    fn test_manylinux_from_str() {
+        assert_eq!(
+            "2010-repair".parse(),
+            Ok(Manylinux::Repair("manylinux2010".to_string()))
+        );
+        assert_eq!(
+            "musllinux_1_1-unchecked".parse(),
+            Ok(Manylinux::Unchecked("musllinux_1_1".to_string()))
+        );
+        assert_eq!(
+            "manylinux_2_24".parse(),
+            Ok(Manylinux::Checked("manylinux_2_24".to_string()))
+        );
+        assert!("-repair".parse::<Manylinux>().is_err());
+        assert!("2014-fixed".parse::<Manylinux>().is_err());
+        assert_eq!(
+            Manylinux::Repair("manylinux2010".to_string()).with_policy("musllinux_1_1"),
+            Manylinux::Repair("musllinux_1_1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_bsd_tags() {
+        let cases = [
+            (
+                "x86_64-unknown-freebsd",
+                "freebsd-12.0-RELEASE-amd64",
+                "freebsd_12_0_amd64",
+            ),
+            (
+                "i686-unknown-freebsd",
+                "freebsd-11.2-RELEASE-i386",
+                "freebsd_11_2_i386",
+            ),
+            (
+                "x86_64-unknown-netbsd",
+                "netbsd-8.1-amd64",
+                "netbsd_8_1_amd64",
+            ),
+            (
+                "x86_64-unknown-openbsd",
+                "openbsd-6.5-amd64",
+                "openbsd_6_5_amd64",
+            ),
+        ];
+        for (triple, sysconfig_platform, tag) in &cases {
+            let target = Target::from_target_triple(Some(triple.to_string()))
+                .unwrap()
+                .with_sysconfig_platform(sysconfig_platform);
+            assert!(target.is_bsd());
+            assert_eq!(target.get_platform_tag(&Manylinux::Off), *tag);
+            assert_eq!(target.get_shared_platform_tag(), None);
+        }
+    }
+
+    #[test]
+    fn test_macos_tags() {
+        let target = Target::from_target_triple(Some("x86_64-apple-darwin".to_string())).unwrap();
+        assert_eq!(
+            target.get_platform_tag(&Manylinux::Off),
+            "macosx_10_7_x86_64"
+        );
+        let cases = [
+            ("10.9", "macosx_10_9_x86_64"),
+            ("10.14.1", "macosx_10_14_x86_64"),
+            ("11", "macosx_11_0_x86_64"),
+            ("11.3", "macosx_11_0_x86_64"),
+            ("12.1.2", "macosx_12_0_x86_64"),
+        ];
+        for (version, tag) in &cases {
+            let target = target.with_os_release(version);
+            assert_eq!(target.get_platform_tag(&Manylinux::Off), *tag);
+        }
+
+        let universal2 = target.with_universal2().unwrap().with_os_release("11.0");
+        assert_eq!(
+            universal2.get_platform_tag(&Manylinux::Off),
+            "macosx_11_0_universal2"
+        );
+        let linux = Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string()));
+        assert!(linux.unwrap().with_universal2().is_err());
+
+        // Apple silicon can build both its own wheels and universal2 wheels
+        let arm64 = Target::from_target_triple(Some("aarch64-apple-darwin".to_string())).unwrap();
+        assert!(arm64.is_macos());
+        assert_eq!(arm64.pointer_width(), 64);
+        assert_eq!(arm64.get_platform_tag(&Manylinux::Off), "macosx_11_0_arm64");
+        assert_eq!(
+            arm64
+                .with_os_release("12.4")
+                .get_platform_tag(&Manylinux::Off),
+            "macosx_12_0_arm64"
+        );
+        assert_eq!(
+            arm64
+                .with_universal2()
+                .unwrap()
+                .get_platform_tag(&Manylinux::Off),
+            "macosx_11_0_universal2"
+        );
+    }
+
+    #[test]
+    fn test_abi3_tag() {
+        let target = Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string()));
+        let manylinux = Manylinux::Checked("manylinux2010".to_string());
+        assert_eq!(
+            target.unwrap().get_abi3_tag(3, 6, &manylinux),
+            "cp36-abi3-manylinux2010_x86_64"
+        );
+        let target = Target::from_target_triple(Some("x86_64-pc-windows-msvc".to_string()));
+        assert_eq!(
+            target.unwrap().get_abi3_tag(3, 7, &manylinux),
+            "cp37-abi3-win_amd64"
+        );
+    }
+
+    #[test]
+    fn test_unsupported_arch() {
+        assert!(
+            Target::from_target_triple(Some("powerpc64-unknown-linux-gnu".to_string())).is_err()
+        );
+        assert!(Target::from_target_triple(Some("aarch64-pc-windows-msvc".to_string())).is_err());
+    }
+}
@@ -0,0 +1,18 @@
+# system configuration generated and used by the sysconfig module
+build_time_vars = {'ABIFLAGS': '',
+ 'BLDSHARED': 'gcc -shared -L/usr/local/lib -Wl,-rpath,/usr/local/lib '
+              '-L/usr/local/lib -Wl,-rpath,/usr/local/lib',
+ 'CC': 'gcc',
+ 'EXT_SUFFIX': '.cpython-38-x86_64-linux-gnu.so',
+ 'HOST_GNU_TYPE': 'x86_64-pc-linux-gnu',
+ 'INCLUDEPY': '/usr/local/include/python3.8',
+ 'LDLIBRARY': 'libpython3.8.so',
+ 'LIBDIR': '/usr/local/lib',
+ 'MACHDEP': 'linux',
+ 'MULTIARCH': 'x86_64-linux-gnu',
+ 'Py_DEBUG': 0,
+ 'Py_ENABLE_SHARED': 1,
+ 'SIZEOF_VOID_P': 8,
+ 'SOABI': 'cpython-38-x86_64-linux-gnu',
+ 'VERSION': '3.8',
+ 'WITH_PYMALLOC': 1}